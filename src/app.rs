@@ -1,24 +1,30 @@
-use clap::{App, Arg, PossibleValue};
+use clap::{App, AppSettings, Arg, PossibleValue};
+
+use task::store::BACKENDS;
+
+/// Values of `--table`, also accepted by the `sort` setting.
+pub const SORT_KEYS: [&str; 12] = [
+    "a", "ra", "s", "rs", "c", "rc", "m", "rm", "d", "rd", "p", "rp",
+];
 
 pub fn get_app() -> App<'static> {
     App::new("task")
         .author("Leon")
         .version("1.0")
         .about("App tracking tasks")
+        .after_help("Tasks can be referred to by their ID, UUID or name, names can be qualified with their project as project/name.")
         .arg(
             Arg::new("table")
                 .help(
-                    "Prints sorted table of tasks, sorting: alphabetical, reverse alphabetical, status, reverse status",
+                    "Prints sorted table of tasks, sorting: alphabetical, status, created, modified, due, priority, prefix with 'r' to reverse, separate with ',' to sort by several keys, defaults to the sort setting",
                 )
                 .long("table")
                 .short('t')
                 .takes_value(true)
                 .value_name("sort_by")
-                .possible_value("a")
-                .possible_value("ra")
-                .possible_value("s")
-                .possible_value("rs")
-                .default_missing_value("a")
+                .possible_values(SORT_KEYS)
+                .use_delimiter(true)
+                .min_values(0)
         )
         .arg(
             Arg::new("info")
@@ -27,54 +33,163 @@ pub fn get_app() -> App<'static> {
                 .alias("print")
                 .short('i')
                 .takes_value(true)
-                .value_name("name"),
+                .value_name("task"),
         )
         .arg(
             Arg::new("delete")
-                .help("Deletes a task")
+                .help("Deletes tasks, separate several with ',' or select them with --filter")
                 .long("delete")
                 .alias("del")
                 .alias("remove")
                 .short('d')
                 .takes_value(true)
-                .value_name("name")
-                .multiple_occurrences(true),
+                .value_name("task")
+                .multiple_occurrences(true)
+                .multiple_values(true)
+                .min_values(0)
+                .use_delimiter(true),
         )
         .arg(
             Arg::new("complete")
-                .help("Sets task's status to completed")
+                .help("Sets tasks' status to completed")
                 .long("complete")
                 .alias("done")
                 .alias("finish")
                 .short('f')
                 .takes_value(true)
-                .value_name("name"),
+                .value_name("task")
+                .multiple_values(true)
+                .min_values(0)
+                .use_delimiter(true),
         )
         .arg(
             Arg::new("active")
-                .help("Sets task's status to active")
+                .help("Sets tasks' status to active")
                 .long("active")
                 .short('a')
                 .takes_value(true)
-                .value_name("name"),
+                .value_name("task")
+                .multiple_values(true)
+                .min_values(0)
+                .use_delimiter(true),
         )
         .arg(
             Arg::new("stop")
-                .help("Sets task's status to completed")
+                .help("Sets tasks' status to stopped")
                 .long("stop")
                 .alias("pause")
                 .alias("halt")
                 .short('s')
                 .takes_value(true)
-                .value_name("name"),
+                .value_name("task")
+                .multiple_values(true)
+                .min_values(0)
+                .use_delimiter(true),
         )
         .arg(
             Arg::new("cancel")
-                .help("Sets task's status to canceled")
+                .help("Sets tasks' status to canceled")
                 .long("cancel")
                 .short('c')
                 .takes_value(true)
-                .value_name("name"),
+                .value_name("task")
+                .multiple_values(true)
+                .min_values(0)
+                .use_delimiter(true),
+        )
+        .arg(
+            Arg::new("tag")
+                .help("Only shows tasks with this tag in the table")
+                .long("tag")
+                .takes_value(true)
+                .value_name("tag")
+                .multiple_occurrences(true)
+                .requires("table"),
+        )
+        .arg(
+            Arg::new("filter")
+                .help("Selects tasks for the table, the status flags and --delete, like: status:active and (+urgent or due.before:fri)")
+                .long("filter")
+                .short('F')
+                .takes_value(true)
+                .value_name("expr")
+                .allow_hyphen_values(true),
+        )
+        .arg(
+            Arg::new("file")
+                .help("Store file to use instead of $TASK_FILE, the directory's own store or the global one")
+                .long("file")
+                .takes_value(true)
+                .value_name("path")
+                .allow_invalid_utf8(true)
+                .global(true),
+        )
+        .arg(
+            Arg::new("global")
+                .help("Uses the global store even inside a directory with its own")
+                .long("global")
+                .short('G')
+                .global(true)
+                .conflicts_with("file"),
+        )
+        .arg(
+            Arg::new("lock-timeout")
+                .help("Seconds to wait for other task processes using the store to finish, defaults to the lock_timeout setting")
+                .long("lock-timeout")
+                .takes_value(true)
+                .value_name("secs")
+                .validator(|secs| match secs.parse::<f64>() {
+                    Ok(secs) if secs >= 0.0 && secs.is_finite() => Ok(()),
+                    _ => Err("expected a number of seconds"),
+                })
+                .global(true),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .help("Shows what a command would do without saving its changes")
+                .long("dry-run")
+                .global(true),
+        )
+        .arg(
+            Arg::new("yes")
+                .help("Applies bulk changes without asking for confirmation")
+                .long("yes")
+                .short('y')
+                .global(true),
+        )
+        .arg(
+            Arg::new("project")
+                .help("Only shows tasks in this project and its subprojects in the table")
+                .long("project")
+                .takes_value(true)
+                .value_name("project")
+                .requires("table"),
+        )
+        .arg(
+            Arg::new("group")
+                .help("Prints a separate table for every project")
+                .long("group")
+                .short('g')
+                .requires("table"),
+        )
+        .arg(
+            Arg::new("tree")
+                .help("Shows subtasks indented under their parent task")
+                .long("tree")
+                .requires("table")
+                .conflicts_with("group"),
+        )
+        .arg(
+            Arg::new("archived")
+                .help("Also shows completed instances of recurring tasks in the table")
+                .long("archived")
+                .requires("table"),
+        )
+        .arg(
+            Arg::new("cascade")
+                .help("Completing a task also completes its open subtasks")
+                .long("cascade")
+                .global(true),
         )
         .subcommand(
             App::new("new")
@@ -82,7 +197,7 @@ pub fn get_app() -> App<'static> {
                 .alias("add")
                 .arg(
                     Arg::new("name")
-                        .help("Name of a new task, tasks can't have same names")
+                        .help("Name of a new task, tasks in the same project can't have same names")
                         .required(true),
                 )
                 .arg(
@@ -93,16 +208,30 @@ pub fn get_app() -> App<'static> {
                         .takes_value(true)
                         .value_name("desc"),
                 )
-                .arg(status_arg()),
+                .arg(project_arg())
+                .arg(parent_arg())
+                .arg(depends_arg())
+                .arg(recur_arg())
+                .arg(status_arg())
+                .arg(due_arg())
+                .arg(priority_arg())
+                .arg(tags_arg())
+                .setting(AppSettings::AllowHyphenValues),
         )
         .subcommand(
             App::new("edit")
                 .about("Edits properties of a task")
                 .arg(
                     Arg::new("task")
-                        .help("Name of a task you want to edit")
+                        .help("IDs or names of the tasks you want to edit, separated with ','")
                         .required(true),
                 )
+                .arg(
+                    Arg::new("filter")
+                        .help("Edits every task matching <task> as a filter expression")
+                        .long("filter")
+                        .short('F'),
+                )
                 .arg(
                     Arg::new("description")
                         .help("Sets description for this task")
@@ -111,6 +240,19 @@ pub fn get_app() -> App<'static> {
                         .value_name("desc")
                         .short('d'),
                 )
+                .arg(project_arg())
+                .arg(parent_arg())
+                .arg(depends_arg())
+                .arg(recur_arg())
+                .arg(
+                    Arg::new("remove-depends")
+                        .help("Removes dependencies of this task")
+                        .long("remove-depends")
+                        .takes_value(true)
+                        .value_name("task")
+                        .multiple_occurrences(true)
+                        .use_delimiter(true),
+                )
                 .arg(
                     Arg::new("rename")
                         .help("Renames this task")
@@ -119,8 +261,127 @@ pub fn get_app() -> App<'static> {
                         .takes_value(true)
                         .value_name("new_name"),
                 )
-                .arg(status_arg()),
+                .arg(status_arg())
+                .arg(due_arg())
+                .arg(priority_arg())
+                .arg(tags_arg())
+                .setting(AppSettings::AllowHyphenValues),
         )
+        .subcommand(
+            App::new("ready")
+                .about("Lists open tasks whose dependencies are all completed"),
+        )
+        .subcommand(
+            App::new("report")
+                .about("Sums the time tracked while tasks were active, per task, tag and day")
+                .arg(
+                    Arg::new("from")
+                        .help("First day of the report, defaults to the first tracked day")
+                        .long("from")
+                        .takes_value(true)
+                        .value_name("date"),
+                )
+                .arg(
+                    Arg::new("to")
+                        .help("Last day of the report, defaults to today")
+                        .long("to")
+                        .takes_value(true)
+                        .value_name("date"),
+                )
+                .arg(
+                    Arg::new("json")
+                        .help("Prints the report as JSON")
+                        .long("json"),
+                ),
+        )
+        .subcommand(
+            App::new("annotate")
+                .about("Adds a timestamped note to a task")
+                .arg(
+                    Arg::new("task")
+                        .help("ID or name of the task")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("text")
+                        .help("Text of the note")
+                        .required(true)
+                        .multiple_values(true),
+                ),
+        )
+        .subcommand(
+            App::new("denotate")
+                .about("Removes a note from a task")
+                .arg(
+                    Arg::new("task")
+                        .help("ID or name of the task")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("note")
+                        .help("Number of the note as shown by --info")
+                        .required(true)
+                        .validator(|note| note.parse::<usize>()),
+                ),
+        )
+        .subcommand(
+            App::new("history")
+                .about("Prints every change made to a task")
+                .arg(
+                    Arg::new("task")
+                        .help("ID, name or UUID of the task, deleted tasks can be found by UUID")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new("export").about("Prints all tasks with their change history as JSON"),
+        )
+        .subcommand(
+            App::new("restore")
+                .about("Lists the backups of earlier saves, or rolls back to one")
+                .arg(
+                    Arg::new("backup")
+                        .help("Number of the backup to restore, 1 is the newest")
+                        .validator(|backup| backup.parse::<usize>()),
+                ),
+        )
+        .subcommand(
+            App::new("init")
+                .about("Creates a task store in .task/ for this directory and the ones below it"),
+        )
+        .subcommand(
+            App::new("migrate-store")
+                .about("Copies the tasks into a new store of another format, the old store is kept under a new name")
+                .arg(
+                    Arg::new("to")
+                        .help("Format of the new store, SQLite stores only read the tasks a command needs")
+                        .long("to")
+                        .takes_value(true)
+                        .value_name("format")
+                        .required(true)
+                        .possible_values(BACKENDS),
+                ),
+        )
+        .subcommand(
+            App::new("config")
+                .about("Shows and changes the settings in the config file")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(App::new("list").about("Prints every setting with its value"))
+                .subcommand(
+                    App::new("get")
+                        .about("Prints the value of a setting")
+                        .arg(Arg::new("key").required(true)),
+                )
+                .subcommand(
+                    App::new("set")
+                        .about("Changes a setting, aliases are set as aliases.<name>")
+                        .arg(Arg::new("key").required(true))
+                        .arg(Arg::new("value").required(true))
+                        .setting(AppSettings::AllowHyphenValues),
+                ),
+        )
+        .subcommand(App::new("undo").about("Reverts the changes made by the last command"))
+        .subcommand(App::new("redo").about("Applies the last undone changes again"))
 }
 
 fn status_arg() -> Arg<'static> {
@@ -133,5 +394,72 @@ fn status_arg() -> Arg<'static> {
         .possible_value(PossibleValue::new("f").help("Task is completed"))
         .possible_value(PossibleValue::new("a").help("Task is currently in progress"))
         .possible_value(PossibleValue::new("s").help("Task is currently not being worked on"))
-        .possible_value(PossibleValue::new("n").help("Task is canceled"))
+        .possible_value(PossibleValue::new("c").alias("n").help("Task is canceled"))
+}
+
+fn due_arg() -> Arg<'static> {
+    Arg::new("due")
+        .help("Sets task's due date: YYYY-MM-DD, today, tomorrow, mon..sun, +3d, +2w, +1m, eow, eom or none")
+        .long("due")
+        .takes_value(true)
+        .value_name("date")
+}
+
+fn priority_arg() -> Arg<'static> {
+    Arg::new("priority")
+        .help("Sets task's priority")
+        .long("priority")
+        .short('p')
+        .takes_value(true)
+        .value_name("priority")
+        .ignore_case(true)
+        .possible_value(PossibleValue::new("H").alias("high").help("High priority"))
+        .possible_value(
+            PossibleValue::new("M")
+                .alias("medium")
+                .help("Medium priority"),
+        )
+        .possible_value(PossibleValue::new("L").alias("low").help("Low priority"))
+        .possible_value(PossibleValue::new("none").help("Removes the priority"))
+}
+
+fn tags_arg() -> Arg<'static> {
+    Arg::new("tags")
+        .help("Adds tags with +tag and removes them with -tag")
+        .value_name("+tag|-tag")
+        .multiple_values(true)
+}
+
+fn project_arg() -> Arg<'static> {
+    Arg::new("project")
+        .help("Puts the task into a project, subprojects are separated by dots: work.backend")
+        .long("project")
+        .takes_value(true)
+        .value_name("project")
+}
+
+fn parent_arg() -> Arg<'static> {
+    Arg::new("parent")
+        .help("Makes the task a subtask of another task, none makes it a top level task")
+        .long("parent")
+        .takes_value(true)
+        .value_name("task")
+}
+
+fn depends_arg() -> Arg<'static> {
+    Arg::new("depends")
+        .help("Makes the task wait until other tasks are completed")
+        .long("depends")
+        .takes_value(true)
+        .value_name("task")
+        .multiple_occurrences(true)
+        .use_delimiter(true)
+}
+
+fn recur_arg() -> Arg<'static> {
+    Arg::new("recur")
+        .help("Makes the task recur: daily, weekly:mon,thu, monthly:15, after:3d (days after completion) or none")
+        .long("recur")
+        .takes_value(true)
+        .value_name("rule")
 }
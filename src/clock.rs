@@ -0,0 +1,23 @@
+use chrono::{DateTime, Utc};
+
+/// Source of the current time for everything the handler records.
+///
+/// Any `Fn() -> DateTime<Utc>` is a clock, which lets callers pin the time.
+pub trait Clock {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// Clock reading the system time.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+impl<F: Fn() -> DateTime<Utc>> Clock for F {
+    fn now(&self) -> DateTime<Utc> {
+        self()
+    }
+}
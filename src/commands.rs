@@ -1,124 +1,563 @@
+use chrono::NaiveDate;
 use clap::ArgMatches;
 
+use task::{
+    date::parse_date, handler::LIST_SEPARATOR, recur::Recurrence, report::TimeReport, result::*,
+    Filter, Priority, Query, SortOrder, Status, TaskEdit, TaskHandler,
+};
+
 use crate::{
-    handler::TaskHandler,
-    output::{Output, SortOrder},
-    result::*,
+    config::{self, Config},
+    output::{Output, TableLayout},
 };
 
-pub fn process_matches(matches: &ArgMatches, handler: &mut TaskHandler, output: &mut Output) {
+pub fn process_matches(
+    matches: &ArgMatches,
+    handler: &mut TaskHandler,
+    output: &mut Output,
+    config: &Config,
+) {
     let mut messages: Vec<Message> = Vec::new();
     let mut errors: Vec<Error> = Vec::new();
-    if let Some(sort) = matches.value_of("table") {
-        table_cmd(handler, output, sort).unwrap_or_else(|e| errors.push(e));
+    let cascade = matches.is_present("cascade");
+    if matches.is_present("table") {
+        let mut sort: Vec<&str> = matches.values_of("table").into_iter().flatten().collect();
+        if sort.is_empty() {
+            sort = config.sort.split(',').collect();
+        }
+        let layout = if matches.is_present("group") {
+            TableLayout::Grouped
+        } else if matches.is_present("tree") {
+            TableLayout::Tree
+        } else {
+            TableLayout::Flat
+        };
+        table_filter(matches, handler)
+            .and_then(|filter| table_cmd(handler, output, sort, filter, layout))
+            .unwrap_or_else(|e| errors.push(e));
     }
 
     if let Some(name) = matches.value_of("info") {
         task_info_cmd(handler, output, name).unwrap_or_else(|e| errors.push(e));
     }
 
-    if let Some(values) = matches.values_of("delete") {
-        let names = values.collect();
-        match delete_cmd(handler, names) {
-            Ok(msg) => messages.push(msg),
-            Err(e) => errors.push(e),
-        };
+    let yes = matches.is_present("yes");
+    if matches.is_present("delete") {
+        let results = delete_cmd(matches, handler, output, yes);
+        split_results(results, &mut messages, &mut errors);
     }
 
-    if let Some(name) = matches.value_of("complete") {
-        match status_cmd(handler, name, "completed") {
-            Ok(msg) => messages.push(msg),
-            Err(e) => errors.push(e),
+    let statuses = [
+        ("complete", Status::Completed),
+        ("active", Status::Active),
+        ("stop", Status::Stopped),
+        ("cancel", Status::Canceled),
+    ];
+    for (arg, status) in statuses {
+        if matches.is_present(arg) {
+            let results = status_cmd(matches, arg, handler, output, status, cascade, yes);
+            split_results(results, &mut messages, &mut errors);
         }
     }
 
-    if let Some(name) = matches.value_of("active") {
-        match status_cmd(handler, name, "active") {
-            Ok(msg) => messages.push(msg),
+    if let Some((name, args)) = matches.subcommand() {
+        let result = match name {
+            "new" => new_cmd(args, handler).map(Some),
+            "edit" => {
+                let results = edit_cmd(args, handler, output, yes);
+                split_results(results, &mut messages, &mut errors);
+                Ok(None)
+            }
+            "ready" => ready_cmd(handler, output).map(|_| None),
+            "report" => report_cmd(args, handler, output).map(|_| None),
+            "history" => history_cmd(args, handler, output).map(|_| None),
+            "export" => export_cmd(handler, output).map(|_| None),
+            "restore" => restore_cmd(args, handler, output),
+            // These are handled before the store is opened.
+            "init" | "migrate-store" | "config" => Ok(None),
+            "undo" => handler.undo().map(Some),
+            "redo" => handler.redo().map(Some),
+            "annotate" => annotate_cmd(args, handler).map(Some),
+            "denotate" => denotate_cmd(args, handler).map(Some),
+            _ => unreachable!("Unreachable!"),
+        };
+        match result {
+            Ok(Some(msg)) => messages.push(msg),
+            Ok(None) => (),
             Err(e) => errors.push(e),
         }
     }
 
-    if let Some(name) = matches.value_of("stop") {
-        match status_cmd(handler, name, "stopped") {
-            Ok(msg) => messages.push(msg),
-            Err(e) => errors.push(e),
+    output.write_all(messages);
+    output.write_all(errors);
+}
+
+/// Bulk changes to more tasks than this show a preview and ask for
+/// confirmation, unless `--yes` is given.
+const CONFIRM_ABOVE: usize = 5;
+
+/// Sorts the outcome of a bulk change into messages and errors.
+fn split_results(
+    results: Result<Vec<Result<Message>>>,
+    messages: &mut Vec<Message>,
+    errors: &mut Vec<Error>,
+) {
+    match results {
+        Ok(results) => {
+            for result in results {
+                match result {
+                    Ok(msg) => messages.push(msg),
+                    Err(e) => errors.push(e),
+                }
+            }
         }
+        Err(e) => errors.push(e),
     }
+}
 
-    if let Some(name) = matches.value_of("cancel") {
-        match status_cmd(handler, name, "canceled") {
-            Ok(msg) => messages.push(msg),
-            Err(e) => errors.push(e),
+/// The tasks a bulk command acts on.
+struct Selection<'a> {
+    ids: Vec<u32>,
+    /// Names that didn't resolve and why.
+    unknown: Vec<(&'a str, Error)>,
+}
+
+/// Selects the tasks named in `keys` plus every task matching the filter.
+fn select_tasks<'a>(
+    keys: Vec<&'a str>,
+    filter: Option<Filter>,
+    handler: &TaskHandler,
+) -> Result<Selection<'a>> {
+    let mut ids = Vec::new();
+    let mut unknown = Vec::new();
+    for key in keys {
+        match handler.find_task(key) {
+            Ok(id) => ids.push(id),
+            Err(e) => unknown.push((key, e)),
         }
     }
-
-    if let Some((name, args)) = matches.subcommand() {
-        let result = match name {
-            "new" => new_cmd(args, handler),
-            "edit" => edit_cmd(args, handler),
-            _ => unreachable!("Unreachable!"),
+    if let Some(filter) = filter {
+        let query = Query {
+            archived: Some(false),
+            ..filter.query()
         };
-        match result {
-            Ok(msg) => messages.push(msg),
-            Err(e) => errors.push(e),
-        }
+        let matching: Vec<u32> = handler
+            .query_content(&query)?
+            .iter()
+            .filter(|task| !task.archived && filter.matches(task))
+            .map(|task| task.id)
+            .collect();
+        ids.extend(matching);
+    }
+    if ids.is_empty() && unknown.is_empty() {
+        return Err(SystemError::NoMatches.into());
     }
+    ids.sort_unstable();
+    ids.dedup();
+    Ok(Selection { ids, unknown })
+}
 
-    output.write_all(messages);
-    output.write_all(errors);
+/// Previews the tasks a bulk change is about to touch and asks before
+/// going ahead, when there are more than `CONFIRM_ABOVE` of them.
+fn confirm(
+    handler: &TaskHandler,
+    output: &mut Output,
+    ids: &[u32],
+    action: &str,
+    yes: bool,
+) -> Result<()> {
+    if yes || ids.len() <= CONFIRM_ABOVE {
+        return Ok(());
+    }
+    let content = ids
+        .iter()
+        .map(|id| handler.get_content(*id))
+        .collect::<Result<Vec<_>>>()?;
+    output.print_table(content, &[SortOrder::Alphabetical], TableLayout::Flat);
+    if output.confirm(&format!("\n{} these {} tasks?", action, ids.len())) {
+        Ok(())
+    } else {
+        Err(SystemError::Aborted.into())
+    }
+}
+
+/// Limits which tasks are shown in the table.
+struct TableFilter {
+    expr: Option<Filter>,
+    /// Shows archived instances of recurring tasks.
+    archived: bool,
+}
+
+/// Combines `--filter` with the `--tag` and `--project` shorthands.
+fn table_filter(matches: &ArgMatches, handler: &TaskHandler) -> Result<TableFilter> {
+    let mut expr = parse_filter(matches, handler)?;
+    for tag in matches.values_of("tag").into_iter().flatten() {
+        expr = Filter::and(expr, Some(Filter::Tag(tag.to_owned())));
+    }
+    if let Some(project) = matches.value_of("project") {
+        expr = Filter::and(expr, Some(Filter::Project(Some(project.to_owned()))));
+    }
+    Ok(TableFilter {
+        expr,
+        archived: matches.is_present("archived"),
+    })
 }
 
-fn table_cmd(handler: &TaskHandler, output: &mut Output, sort: &str) -> Result<()> {
-    if !handler.is_empty() {
-        let content = handler.all_content();
-        match sort {
-                "a" => output.print_table(content, SortOrder::Alphabetical),
-                "ra" => output.print_table(content, SortOrder::ReverseAlphabetical),
-                "s" => output.print_table(content, SortOrder::Status),
-                "rs" => output.print_table(content, SortOrder::ReverseStatus),
-                &_ => unreachable!("Unreachable!"),
+fn table_cmd(
+    handler: &TaskHandler,
+    output: &mut Output,
+    sort: Vec<&str>,
+    filter: TableFilter,
+    layout: TableLayout,
+) -> Result<()> {
+    if !handler.is_empty()? {
+        let orders: Vec<SortOrder> = sort.into_iter().map(sort_order).collect();
+        let mut query = filter
+            .expr
+            .as_ref()
+            .map_or_else(Query::default, Filter::query);
+        if !filter.archived {
+            query.archived = Some(false);
         }
+        let mut content = handler.query_content(&query)?;
+        content.retain(|task| filter.expr.as_ref().is_none_or(|expr| expr.matches(task)));
+        output.print_table(content, &orders, layout);
         return Ok(());
     }
     Err(SystemError::Empty.into())
 }
 
-fn task_info_cmd(handler: &TaskHandler, output: &mut Output, name: &str) -> Result<()> {
-    let task = handler.get_content(name)?;
-    output.print_task(task);
+fn sort_order(sort: &str) -> SortOrder {
+    match sort {
+        "a" => SortOrder::Alphabetical,
+        "ra" => SortOrder::ReverseAlphabetical,
+        "s" => SortOrder::Status,
+        "rs" => SortOrder::ReverseStatus,
+        "c" => SortOrder::Created,
+        "rc" => SortOrder::ReverseCreated,
+        "m" => SortOrder::Modified,
+        "rm" => SortOrder::ReverseModified,
+        "d" => SortOrder::Due,
+        "rd" => SortOrder::ReverseDue,
+        "p" => SortOrder::Priority,
+        "rp" => SortOrder::ReversePriority,
+        &_ => unreachable!("Unreachable!"),
+    }
+}
+
+/// Prints the open tasks whose dependencies are all completed, most
+/// important first.
+fn ready_cmd(handler: &TaskHandler, output: &mut Output) -> Result<()> {
+    if handler.is_empty()? {
+        return Err(SystemError::Empty.into());
+    }
+    let orders = [SortOrder::Priority, SortOrder::Due, SortOrder::Alphabetical];
+    output.print_table(handler.ready_content()?, &orders, TableLayout::Flat);
+    Ok(())
+}
+
+fn report_cmd(args: &ArgMatches, handler: &TaskHandler, output: &mut Output) -> Result<()> {
+    let today = handler.today();
+    let from = args
+        .value_of("from")
+        .map(|from| parse_date(from, today))
+        .transpose()?;
+    let to = match args.value_of("to") {
+        Some(to) => parse_date(to, today)?,
+        None => today,
+    };
+    let report = TimeReport::new(&handler.all_content()?, from, to, handler.now())?;
+    if args.is_present("json") {
+        output.write(format!("{}\n", serde_json::to_string_pretty(&report)?));
+    } else {
+        output.print_report(&report);
+    }
+    Ok(())
+}
+
+fn history_cmd(args: &ArgMatches, handler: &TaskHandler, output: &mut Output) -> Result<()> {
+    output.print_history(&handler.history(args.value_of("task").unwrap())?);
+    Ok(())
+}
+
+fn export_cmd(handler: &TaskHandler, output: &mut Output) -> Result<()> {
+    output.write(format!("{}\n", handler.export_json()?));
+    Ok(())
+}
+
+/// Lists the backups, or restores one when its number is given.
+fn restore_cmd(
+    args: &ArgMatches,
+    handler: &mut TaskHandler,
+    output: &mut Output,
+) -> Result<Option<Message>> {
+    match args.value_of("backup") {
+        Some(_) => handler.restore(args.value_of_t_or_exit("backup")).map(Some),
+        None => {
+            output.print_backups(&handler.backups());
+            Ok(None)
+        }
+    }
+}
+
+/// Runs without a store, it only reads and writes the config file.
+pub fn config_cmd(
+    args: &ArgMatches,
+    config: &Config,
+    output: &mut Output,
+) -> Result<Option<Message>> {
+    match args.subcommand() {
+        Some(("list", _)) => {
+            for (key, value) in config.list()? {
+                output.write(format!("{} = {}\n", key, value));
+            }
+            Ok(None)
+        }
+        Some(("get", args)) => {
+            output.write(format!("{}\n", config.get(args.value_of("key").unwrap())?));
+            Ok(None)
+        }
+        Some(("set", args)) => {
+            let key = args.value_of("key").unwrap();
+            let value = args.value_of("value").unwrap();
+            config::set(key, value)?;
+            Ok(Some(Message::ConfigSet(key.to_owned(), value.to_owned())))
+        }
+        _ => unreachable!("Unreachable!"),
+    }
+}
+
+fn task_info_cmd(handler: &TaskHandler, output: &mut Output, key: &str) -> Result<()> {
+    let id = handler.find_task(key)?;
+    output.print_task(
+        handler.get_content(id)?,
+        handler.subtasks(id)?,
+        handler.now(),
+    );
     Ok(())
 }
 
 fn new_cmd(args: &ArgMatches, handler: &mut TaskHandler) -> Result<Message> {
     let name = args.value_of("name").unwrap();
-    let msg = handler.create_task(name)?;
-    let desc = args.value_of("description");
-    let status = args.value_of("status");
-    handler.edit_task(name, desc, status, None)?;
-    Ok(msg)
+    let (add_tags, remove_tags) = parse_tags(args)?;
+    let edit = TaskEdit {
+        desc: args.value_of("description"),
+        status: parse_status(args)?,
+        due: parse_due(args, handler)?,
+        priority: parse_priority(args)?,
+        parent: parse_parent(args, handler)?,
+        add_tags,
+        remove_tags,
+        add_depends: parse_tasks(args, "depends", handler)?,
+        recur: parse_recur(args)?,
+        ..Default::default()
+    };
+    let id = handler.create_task(name, args.value_of("project"))?;
+    if let Err(e) = handler.edit_task(id, edit) {
+        handler.delete_task(id)?;
+        return Err(e);
+    }
+    Ok(Message::CreatedTask(name.to_owned(), id))
 }
 
-fn status_cmd(handler: &mut TaskHandler, name: &str, status: &str) -> Result<Message> {
-    handler.edit_task(name, None, Some(status), None)
+fn status_cmd(
+    matches: &ArgMatches,
+    arg: &str,
+    handler: &mut TaskHandler,
+    output: &mut Output,
+    status: Status,
+    cascade: bool,
+    yes: bool,
+) -> Result<Vec<Result<Message>>> {
+    let keys = matches.values_of(arg).into_iter().flatten().collect();
+    let Selection { ids, unknown } = select_tasks(keys, parse_filter(matches, handler)?, handler)?;
+    let mut results: Vec<Result<Message>> = unknown.into_iter().map(|(_, e)| Err(e)).collect();
+    let action = match status {
+        Status::Completed => "Complete",
+        Status::Active => "Start",
+        Status::Stopped => "Stop",
+        Status::Canceled => "Cancel",
+    };
+    confirm(handler, output, &ids, action, yes)?;
+    for id in ids {
+        let edit = TaskEdit {
+            status: Some(status),
+            cascade,
+            ..Default::default()
+        };
+        results.push(handler.edit_task(id, edit));
+    }
+    Ok(results)
+}
+
+fn edit_cmd(
+    args: &ArgMatches,
+    handler: &mut TaskHandler,
+    output: &mut Output,
+    yes: bool,
+) -> Result<Vec<Result<Message>>> {
+    let task = args.value_of("task").unwrap();
+    let Selection { ids, unknown } = if args.is_present("filter") {
+        select_tasks(vec![], Some(Filter::parse(task, handler.today())?), handler)?
+    } else {
+        select_tasks(task.split(LIST_SEPARATOR).collect(), None, handler)?
+    };
+    let selected = ids.len() + unknown.len();
+    if args.is_present("rename") && selected > 1 {
+        return Err(SystemError::RenameMany(selected).into());
+    }
+    let (add_tags, remove_tags) = parse_tags(args)?;
+    let edit = TaskEdit {
+        desc: args.value_of("description"),
+        status: parse_status(args)?,
+        name: args.value_of("rename"),
+        project: parse_project(args),
+        due: parse_due(args, handler)?,
+        priority: parse_priority(args)?,
+        parent: parse_parent(args, handler)?,
+        add_tags,
+        remove_tags,
+        add_depends: parse_tasks(args, "depends", handler)?,
+        remove_depends: parse_tasks(args, "remove-depends", handler)?,
+        recur: parse_recur(args)?,
+        cascade: args.is_present("cascade"),
+    };
+    let mut results: Vec<Result<Message>> = unknown.into_iter().map(|(_, e)| Err(e)).collect();
+    confirm(handler, output, &ids, "Edit", yes)?;
+    for id in ids {
+        results.push(handler.edit_task(id, edit.clone()));
+    }
+    Ok(results)
 }
 
-fn edit_cmd(args: &ArgMatches, handler: &mut TaskHandler) -> Result<Message> {
-    let name = args.value_of("task").unwrap();
-    let desc = args.value_of("description");
-    let status = args.value_of("status");
-    let new_name = args.value_of("rename");
-    handler.edit_task(name, desc, status, new_name)
+fn annotate_cmd(args: &ArgMatches, handler: &mut TaskHandler) -> Result<Message> {
+    let id = handler.find_task(args.value_of("task").unwrap())?;
+    let text: Vec<&str> = args.values_of("text").unwrap().collect();
+    handler.annotate(id, &text.join(" "))
 }
 
-fn delete_cmd(handler: &mut TaskHandler, names: Vec<&str>) -> Result<Message> {
+fn denotate_cmd(args: &ArgMatches, handler: &mut TaskHandler) -> Result<Message> {
+    let id = handler.find_task(args.value_of("task").unwrap())?;
+    handler.denotate(id, args.value_of_t_or_exit("note"))
+}
+
+fn delete_cmd(
+    matches: &ArgMatches,
+    handler: &mut TaskHandler,
+    output: &mut Output,
+    yes: bool,
+) -> Result<Vec<Result<Message>>> {
+    let keys = matches.values_of("delete").into_iter().flatten().collect();
+    let Selection { ids, unknown } = select_tasks(keys, parse_filter(matches, handler)?, handler)?;
+    confirm(handler, output, &ids, "Delete", yes)?;
+    let mut results: Vec<Result<Message>> = unknown.into_iter().map(|(_, e)| Err(e)).collect();
     let mut deleted = Vec::new();
-    let mut errs = Vec::new();
-    for name in names {
-        match handler.delete_task(name) {
-            Ok(_) => deleted.push(name.to_string()),
-            Err(_) => errs.push(name.to_string()),
+    for id in ids {
+        match handler.delete_task(id) {
+            Ok(name) => deleted.push(name),
+            Err(e) => results.push(Err(e)),
+        };
+    }
+    if !deleted.is_empty() {
+        results.push(Ok(Message::DeletedTasks(deleted)));
+    }
+    Ok(results)
+}
+
+fn parse_filter(args: &ArgMatches, handler: &TaskHandler) -> Result<Option<Filter>> {
+    args.value_of("filter")
+        .map(|filter| Filter::parse(filter, handler.today()))
+        .transpose()
+}
+
+fn parse_status(args: &ArgMatches) -> Result<Option<Status>> {
+    args.value_of("status").map(str::parse).transpose()
+}
+
+/// `none` removes the due date.
+fn parse_due(args: &ArgMatches, handler: &TaskHandler) -> Result<Option<Option<NaiveDate>>> {
+    match args.value_of("due") {
+        Some("none") => Ok(Some(None)),
+        Some(due) => Ok(Some(Some(parse_date(due, handler.today())?))),
+        None => Ok(None),
+    }
+}
+
+/// `none` removes the priority.
+/// The flag ignores case, so `NONE` removes the priority as well.
+fn parse_priority(args: &ArgMatches) -> Result<Option<Option<Priority>>> {
+    match args.value_of("priority").map(str::to_lowercase).as_deref() {
+        Some("none") => Ok(Some(None)),
+        Some(priority) => Ok(Some(Some(priority.parse()?))),
+        None => Ok(None),
+    }
+}
+
+/// Splits `+tag` and `-tag` arguments into tags to add and to remove.
+fn parse_tags(args: &ArgMatches) -> Result<(Vec<&str>, Vec<&str>)> {
+    let mut add = Vec::new();
+    let mut remove = Vec::new();
+    for arg in args.values_of("tags").into_iter().flatten() {
+        if let Some(tag) = arg.strip_prefix('+') {
+            add.push(tag);
+        } else if let Some(tag) = arg.strip_prefix('-') {
+            remove.push(tag);
+        } else {
+            return Err(SystemError::InvalidTag(arg.to_owned()).into());
+        }
+    }
+    Ok((add, remove))
+}
+
+/// `none` moves the task out of its project.
+fn parse_project(args: &ArgMatches) -> Option<Option<&str>> {
+    match args.value_of("project") {
+        Some("none") => Some(None),
+        project => project.map(Some),
+    }
+}
+
+/// `none` makes the task a top level task.
+fn parse_parent(args: &ArgMatches, handler: &TaskHandler) -> Result<Option<Option<u32>>> {
+    match args.value_of("parent") {
+        Some("none") => Ok(Some(None)),
+        Some(parent) => Ok(Some(Some(handler.find_task(parent)?))),
+        None => Ok(None),
+    }
+}
+
+/// Resolves every task given to a multi-value argument.
+fn parse_tasks(args: &ArgMatches, arg: &str, handler: &TaskHandler) -> Result<Vec<u32>> {
+    args.values_of(arg)
+        .into_iter()
+        .flatten()
+        .map(|key| handler.find_task(key))
+        .collect()
+}
+
+/// `none` stops the task from recurring.
+fn parse_recur(args: &ArgMatches) -> Result<Option<Option<Recurrence>>> {
+    match args.value_of("recur") {
+        Some("none") => Ok(Some(None)),
+        Some(recur) => Ok(Some(Some(recur.parse()?))),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::get_app;
+
+    #[test]
+    fn priorities_ignore_case() {
+        let priority = |value: &str| {
+            let matches = get_app().get_matches_from(["task", "edit", "write", "-p", value]);
+            parse_priority(matches.subcommand_matches("edit").unwrap()).unwrap()
         };
+        assert_eq!(priority("NONE"), Some(None));
+        assert_eq!(priority("None"), Some(None));
+        assert_eq!(priority("High"), Some(Some(Priority::High)));
+        assert_eq!(priority("m"), Some(Some(Priority::Medium)));
     }
-    Ok(Message::DeletedTasks(deleted, errs))
 }
@@ -0,0 +1,308 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use chrono::format::{Item, StrftimeItems};
+
+use task::{
+    date::{ISO_FORMAT, TIME_FORMAT},
+    result::{ConfigError, Result, SystemError},
+    Status,
+};
+
+use crate::app::SORT_KEYS;
+
+/// Every setting except the aliases, which are `aliases.<name>`.
+const KEYS: &[&str] = &[
+    "sort",
+    "table_width",
+    "info_width",
+    "color",
+    "default_status",
+    "date_format",
+    "time_format",
+    "lock_timeout",
+    "user",
+    "store",
+];
+
+/// Settings read from `config.toml` in the user's config dir
+/// (`$XDG_CONFIG_HOME/task/` on Linux), every key is optional.
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Sort order for `--table` without a value, written like the flag: `p,d`.
+    pub sort: String,
+    /// Width of the task table and the history.
+    pub table_width: u16,
+    /// Width of `--info`, reports and other key/value tables.
+    pub info_width: u16,
+    /// `false` turns colors off, otherwise they are used on terminals.
+    pub color: bool,
+    /// Status new tasks start in.
+    pub default_status: Status,
+    /// chrono format for dates, also accepted when parsing them.
+    pub date_format: String,
+    /// chrono format for points in time.
+    pub time_format: String,
+    /// Seconds to wait for other runs using the store, `--lock-timeout`
+    /// overrides it.
+    pub lock_timeout: f64,
+    /// Who the change history credits, `$USER` when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Global store, used outside of directories with their own.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub store: Option<PathBuf>,
+    /// Expanded when given as the first argument: `done = "--complete"`.
+    pub aliases: BTreeMap<String, String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            sort: String::from("a"),
+            table_width: 67,
+            info_width: 40,
+            color: true,
+            default_status: Status::default(),
+            date_format: String::from(ISO_FORMAT),
+            time_format: String::from(TIME_FORMAT),
+            lock_timeout: 5.0,
+            user: None,
+            store: None,
+            aliases: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        let mut path = dirs::config_dir()?;
+        path.push("task");
+        path.push("config.toml");
+        Some(path)
+    }
+
+    /// Reads the config file, a missing file means the defaults.
+    pub fn load() -> Result<Self> {
+        let table = read_table(Self::path().as_deref())?;
+        from_table(table)
+    }
+
+    /// Replaces the first argument with the words of the alias it names.
+    pub fn expand_aliases(&self, mut args: Vec<String>) -> Vec<String> {
+        if let Some(expansion) = args.get(1).and_then(|arg| self.aliases.get(arg)) {
+            let words: Vec<String> = expansion.split_whitespace().map(str::to_owned).collect();
+            args.splice(1..2, words);
+        }
+        args
+    }
+
+    fn validate(&self) -> Result<()> {
+        if let Some(key) = self.sort.split(',').find(|key| !SORT_KEYS.contains(key)) {
+            return Err(invalid("sort", format!("\"{}\" is not a sort key", key)));
+        }
+        for (key, format) in [
+            ("date_format", &self.date_format),
+            ("time_format", &self.time_format),
+        ] {
+            if StrftimeItems::new(format).any(|item| item == Item::Error) {
+                return Err(invalid(
+                    key,
+                    format!("\"{}\" is not a valid format", format),
+                ));
+            }
+        }
+        if !(self.lock_timeout >= 0.0 && self.lock_timeout.is_finite()) {
+            return Err(invalid("lock_timeout", String::from("expected seconds")));
+        }
+        Ok(())
+    }
+
+    /// Every setting with its current value as `key = value` lines, aliases
+    /// as `aliases.name`.
+    pub fn list(&self) -> Result<Vec<(String, String)>> {
+        let table = toml::Table::try_from(self).map_err(|e| invalid("", e.to_string()))?;
+        let mut entries = Vec::new();
+        for (key, value) in table {
+            match value {
+                toml::Value::Table(aliases) => {
+                    for (name, expansion) in aliases {
+                        entries.push((format!("{}.{}", key, name), expansion.to_string()));
+                    }
+                }
+                value => entries.push((key, value.to_string())),
+            }
+        }
+        Ok(entries)
+    }
+
+    /// The value of a setting, empty when it isn't set.
+    pub fn get(&self, key: &str) -> Result<String> {
+        check_key(key)?;
+        Ok(self
+            .list()?
+            .into_iter()
+            .find(|(k, _)| k == key)
+            .map_or(String::new(), |(_, value)| value))
+    }
+}
+
+/// Changes one setting in the config file, keeping the others as written.
+/// Values are read as TOML when possible, `90` is a number and `false` a
+/// boolean, anything else is taken as a string.
+pub fn set(key: &str, value: &str) -> Result<()> {
+    let path = Config::path().ok_or(SystemError::NoConfigDir)?;
+    set_in(&path, key, value)
+}
+
+/// [`set`] for the config file at `path`.
+fn set_in(path: &Path, key: &str, value: &str) -> Result<()> {
+    check_key(key)?;
+    let mut table = read_table(Some(path))?;
+    let value = toml::from_str::<toml::Table>(&format!("value = {}", value))
+        .ok()
+        .and_then(|mut t| t.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_owned()));
+    match key.split_once('.') {
+        Some(("aliases", name)) => {
+            let aliases = table
+                .entry("aliases")
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            match aliases {
+                toml::Value::Table(aliases) => aliases.insert(name.to_owned(), value),
+                _ => return Err(invalid("aliases", String::from("expected a table"))),
+            };
+        }
+        _ => {
+            table.insert(key.to_owned(), value);
+        }
+    }
+    let config: Config = table
+        .clone()
+        .try_into()
+        .map_err(|e: toml::de::Error| invalid(key, e.message().to_owned()))?;
+    config.validate()?;
+
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    std::fs::write(path, table.to_string())?;
+    Ok(())
+}
+
+fn check_key(key: &str) -> Result<()> {
+    let alias = key
+        .strip_prefix("aliases.")
+        .is_some_and(|name| !name.is_empty());
+    if alias || KEYS.contains(&key) {
+        Ok(())
+    } else {
+        Err(ConfigError::UnknownKey(key.to_owned()).into())
+    }
+}
+
+fn read_table(path: Option<&Path>) -> Result<toml::Table> {
+    match path {
+        Some(path) if path.is_file() => {
+            let raw = std::fs::read_to_string(path)?;
+            raw.parse::<toml::Table>()
+                .map_err(|e| ConfigError::Parse(path.to_path_buf(), e.to_string()).into())
+        }
+        _ => Ok(toml::Table::new()),
+    }
+}
+
+fn from_table(table: toml::Table) -> Result<Config> {
+    let path = Config::path().unwrap_or_default();
+    let config: Config = table
+        .try_into()
+        .map_err(|e: toml::de::Error| ConfigError::Parse(path, e.message().to_owned()))?;
+    config.validate()?;
+    Ok(config)
+}
+
+fn invalid(key: &str, reason: String) -> task::Error {
+    ConfigError::InvalidValue(key.to_owned(), reason).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(path: &Path) -> Config {
+        from_table(read_table(Some(path)).unwrap()).unwrap()
+    }
+
+    fn unknown(result: Result<impl std::fmt::Debug>) -> bool {
+        result.is_err_and(|err| {
+            matches!(
+                err.kind(),
+                task::ErrorType::Config(ConfigError::UnknownKey(_))
+            )
+        })
+    }
+
+    #[test]
+    fn defaults_keep_the_old_layout() {
+        let config = Config::default();
+        assert_eq!(config.get("table_width").unwrap(), "67");
+        assert_eq!(config.get("info_width").unwrap(), "40");
+        assert_eq!(config.get("user").unwrap(), "");
+    }
+
+    #[test]
+    fn set_keeps_the_other_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("task/config.toml");
+        set_in(&path, "sort", "p,d").unwrap();
+        set_in(&path, "table_width", "100").unwrap();
+        set_in(&path, "aliases.done", "--complete").unwrap();
+        set_in(&path, "aliases.today", "-t --filter due:today").unwrap();
+
+        let config = read(&path);
+        assert_eq!(config.sort, "p,d");
+        assert_eq!(config.table_width, 100);
+        assert_eq!(config.info_width, 40);
+        assert_eq!(config.get("aliases.done").unwrap(), "\"--complete\"");
+        assert_eq!(config.aliases.len(), 2);
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        set_in(&path, "sort", "p").unwrap();
+        let saved = std::fs::read_to_string(&path).unwrap();
+
+        assert!(unknown(set_in(&path, "colour", "false")));
+        assert!(unknown(set_in(&path, "aliases.", "-t")));
+        assert!(unknown(Config::default().get("colour")));
+        assert!(set_in(&path, "sort", "x").is_err());
+        assert!(set_in(&path, "table_width", "wide").is_err());
+        assert!(set_in(&path, "lock_timeout", "-1").is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), saved);
+
+        std::fs::write(&path, "colour = false\n").unwrap();
+        assert!(from_table(read_table(Some(&path)).unwrap()).is_err());
+    }
+
+    #[test]
+    fn expands_aliases_in_first_position() {
+        let mut config = Config::default();
+        config
+            .aliases
+            .insert(String::from("done"), String::from("--complete --yes"));
+        let args =
+            |args: &[&str]| -> Vec<String> { args.iter().map(|arg| arg.to_string()).collect() };
+
+        assert_eq!(
+            config.expand_aliases(args(&["task", "done", "write"])),
+            args(&["task", "--complete", "--yes", "write"])
+        );
+        assert_eq!(
+            config.expand_aliases(args(&["task", "new", "done"])),
+            args(&["task", "new", "done"])
+        );
+    }
+}
@@ -0,0 +1,165 @@
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Datelike, Days, Local, Months, NaiveDate, Utc, Weekday};
+
+use crate::result::{Result, SystemError};
+
+pub const ISO_FORMAT: &str = "%Y-%m-%d";
+pub const TIME_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+/// Date and time formats from the config, set once at startup.
+static FORMATS: OnceLock<(String, String)> = OnceLock::new();
+
+/// Sets the formats used to print dates and times, the date format is
+/// also accepted when parsing dates.
+pub fn set_formats(date: &str, time: &str) {
+    FORMATS.get_or_init(|| (date.to_owned(), time.to_owned()));
+}
+
+fn date_format() -> &'static str {
+    FORMATS.get().map_or(ISO_FORMAT, |(date, _)| date)
+}
+
+fn time_format() -> &'static str {
+    FORMATS.get().map_or(TIME_FORMAT, |(_, time)| time)
+}
+
+/// Parses a due date relative to `today`.
+///
+/// Accepts ISO dates (`2022-03-14`), `today`, `tomorrow`, weekday names
+/// (`fri`, `friday`) meaning the next such day, offsets (`+3d`, `+2w`, `+1m`),
+/// `eow` (end of week, Sunday) and `eom` (end of month).
+pub fn parse_date(input: &str, today: NaiveDate) -> Result<NaiveDate> {
+    let lower = input.trim().to_lowercase();
+    let date = match lower.as_str() {
+        "today" => Some(today),
+        "tomorrow" => today.succ_opt(),
+        "eow" => {
+            today.checked_add_days(Days::new(6 - today.weekday().num_days_from_monday() as u64))
+        }
+        "eom" => end_of_month(today),
+        s if s.starts_with('+') => parse_offset(&s[1..], today),
+        s => match parse_weekday(s) {
+            Some(weekday) => next_weekday(today, weekday),
+            None => NaiveDate::parse_from_str(s, ISO_FORMAT)
+                .or_else(|_| NaiveDate::parse_from_str(s, date_format()))
+                .ok(),
+        },
+    };
+    date.ok_or_else(|| SystemError::InvalidDate(input.to_owned()).into())
+}
+
+pub fn format_date(date: NaiveDate) -> String {
+    date.format(date_format()).to_string()
+}
+
+/// Formats a point in time in the local timezone.
+pub fn format_time(time: DateTime<Utc>) -> String {
+    time.with_timezone(&Local).format(time_format()).to_string()
+}
+
+fn parse_offset(s: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let unit = s.chars().last()?;
+    let n: u32 = s[..s.len() - unit.len_utf8()].parse().ok()?;
+    match unit {
+        'd' => today.checked_add_days(Days::new(n as u64)),
+        'w' => today.checked_add_days(Days::new(n as u64 * 7)),
+        'm' => today.checked_add_months(Months::new(n)),
+        _ => None,
+    }
+}
+
+pub fn parse_weekday(s: &str) -> Option<Weekday> {
+    const DAYS: [(&str, &str, Weekday); 7] = [
+        ("mon", "monday", Weekday::Mon),
+        ("tue", "tuesday", Weekday::Tue),
+        ("wed", "wednesday", Weekday::Wed),
+        ("thu", "thursday", Weekday::Thu),
+        ("fri", "friday", Weekday::Fri),
+        ("sat", "saturday", Weekday::Sat),
+        ("sun", "sunday", Weekday::Sun),
+    ];
+    DAYS.iter()
+        .find(|(short, long, _)| s == *short || s == *long)
+        .map(|(_, _, day)| *day)
+}
+
+/// The next `weekday` after `today`, a week ahead if today is that day.
+fn next_weekday(today: NaiveDate, weekday: Weekday) -> Option<NaiveDate> {
+    let from = today.weekday().num_days_from_monday();
+    let to = weekday.num_days_from_monday();
+    let days = (to + 7 - from - 1) % 7 + 1;
+    today.checked_add_days(Days::new(days as u64))
+}
+
+pub fn end_of_month(today: NaiveDate) -> Option<NaiveDate> {
+    let first = today.with_day(1)?;
+    first.checked_add_months(Months::new(1))?.pred_opt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn parses_named_days() {
+        let friday = date(2024, 3, 1);
+        assert_eq!(parse_date("today", friday).unwrap(), friday);
+        assert_eq!(parse_date("Tomorrow", friday).unwrap(), date(2024, 3, 2));
+        assert_eq!(
+            parse_date("2024-12-24", friday).unwrap(),
+            date(2024, 12, 24)
+        );
+        assert_eq!(parse_date("eom", friday).unwrap(), date(2024, 3, 31));
+    }
+
+    #[test]
+    fn end_of_week_on_sunday_is_today() {
+        let sunday = date(2024, 3, 3);
+        assert_eq!(parse_date("eow", sunday).unwrap(), sunday);
+        assert_eq!(parse_date("eow", date(2024, 2, 26)).unwrap(), sunday);
+    }
+
+    #[test]
+    fn weekday_names_mean_the_next_one() {
+        let friday = date(2024, 3, 1);
+        assert_eq!(parse_date("fri", friday).unwrap(), date(2024, 3, 8));
+        assert_eq!(parse_date("saturday", friday).unwrap(), date(2024, 3, 2));
+        assert_eq!(parse_date("thu", friday).unwrap(), date(2024, 3, 7));
+    }
+
+    #[test]
+    fn offsets_clamp_to_the_end_of_shorter_months() {
+        let end_of_january = date(2024, 1, 31);
+        assert_eq!(
+            parse_date("+1m", end_of_january).unwrap(),
+            date(2024, 2, 29)
+        );
+        assert_eq!(
+            parse_date("+1m", date(2023, 1, 31)).unwrap(),
+            date(2023, 2, 28)
+        );
+        assert_eq!(parse_date("+3d", end_of_january).unwrap(), date(2024, 2, 3));
+        assert_eq!(
+            parse_date("+2w", end_of_january).unwrap(),
+            date(2024, 2, 14)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_dates() {
+        let today = date(2024, 3, 1);
+        for input in ["", "+", "+3", "+3y", "+-1d", "someday", "2024-02-30"] {
+            assert!(
+                parse_date(input, today).is_err(),
+                "{:?} was accepted",
+                input
+            );
+        }
+        assert!(parse_date("+1d", NaiveDate::MAX).is_err());
+    }
+}
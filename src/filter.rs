@@ -0,0 +1,438 @@
+use std::{iter::Peekable, vec::IntoIter};
+
+use chrono::NaiveDate;
+use regex::Regex;
+
+use crate::{
+    date::parse_date,
+    handler::in_project,
+    result::{Result, SystemError},
+    store::Query,
+    task::{Priority, Status, Task},
+};
+
+/// A parsed filter expression like `status:active and (+urgent or due.before:fri)`.
+///
+/// Terms next to each other without an operator are joined with `and`,
+/// which binds tighter than `or`.
+#[derive(Debug)]
+pub enum Filter {
+    Status(Status),
+    /// `None` matches tasks outside of any project.
+    Project(Option<String>),
+    Tag(String),
+    Priority(Option<Priority>),
+    /// `None` matches tasks without a due date.
+    Due(Option<NaiveDate>),
+    DueBefore(NaiveDate),
+    DueAfter(NaiveDate),
+    Name(Regex),
+    Desc(Regex),
+    Not(Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+}
+
+impl Filter {
+    /// Parses a filter expression, relative dates are resolved against `today`.
+    pub fn parse(input: &str, today: NaiveDate) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?.into_iter().peekable(),
+            today,
+        };
+        let filter = parser.or()?;
+        match parser.tokens.next() {
+            None => Ok(filter),
+            Some(token) => Err(invalid(format!("unexpected \"{}\"", token))),
+        }
+    }
+
+    /// Joins two filters with `and`, either may be missing.
+    pub fn and(a: Option<Self>, b: Option<Self>) -> Option<Self> {
+        match (a, b) {
+            (Some(a), Some(b)) => Some(Filter::And(Box::new(a), Box::new(b))),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// A query selecting at least every task the filter matches, so the
+    /// store can skip tasks that can't match. The filter still has to be
+    /// applied to what it returns.
+    pub fn query(&self) -> Query {
+        let mut query = Query::default();
+        self.narrow(&mut query);
+        query
+    }
+
+    /// Adds the terms that have to hold for every match to the query.
+    fn narrow(&self, query: &mut Query) {
+        match self {
+            Filter::And(a, b) => {
+                a.narrow(query);
+                b.narrow(query);
+            }
+            Filter::Tag(tag) => query.tags.push(tag.clone()),
+            Filter::DueBefore(date) if query.due_before.is_none_or(|d| date < &d) => {
+                query.due_before = Some(*date);
+            }
+            Filter::DueAfter(date) if query.due_after.is_none_or(|d| date > &d) => {
+                query.due_after = Some(*date);
+            }
+            filter if query.statuses.is_empty() => {
+                query.statuses = filter.statuses().unwrap_or_default();
+            }
+            _ => (),
+        }
+    }
+
+    /// The statuses of a filter that only selects by status, like
+    /// `status:active or status:stopped`.
+    fn statuses(&self) -> Option<Vec<Status>> {
+        match self {
+            Filter::Status(status) => Some(vec![*status]),
+            Filter::Or(a, b) => {
+                let mut statuses = a.statuses()?;
+                statuses.extend(b.statuses()?);
+                Some(statuses)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn matches(&self, task: &Task) -> bool {
+        match self {
+            Filter::Status(status) => task.status == *status,
+            Filter::Project(None) => task.project.is_none(),
+            Filter::Project(Some(project)) => in_project(task.project.as_deref(), project),
+            Filter::Tag(tag) => task.tags.contains(tag),
+            Filter::Priority(priority) => task.priority == *priority,
+            Filter::Due(due) => task.due == *due,
+            Filter::DueBefore(date) => task.due.is_some_and(|due| due < *date),
+            Filter::DueAfter(date) => task.due.is_some_and(|due| due > *date),
+            Filter::Name(regex) => regex.is_match(&task.name),
+            Filter::Desc(regex) => regex.is_match(&task.desc),
+            Filter::Not(filter) => !filter.matches(task),
+            Filter::And(a, b) => a.matches(task) && b.matches(task),
+            Filter::Or(a, b) => a.matches(task) || b.matches(task),
+        }
+    }
+}
+
+fn invalid(reason: String) -> crate::result::Error {
+    SystemError::InvalidFilter(reason).into()
+}
+
+/// Splits the input into words and parentheses. Parentheses inside a word,
+/// like in `name~(a|b)`, stay part of it as long as they are balanced, and
+/// double quotes keep spaces in a value: `desc~"call back"`.
+fn tokenize(input: &str) -> Result<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut depth = 0;
+    let mut quoted = false;
+    for c in input.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            _ if quoted => word.push(c),
+            '(' if word.is_empty() => tokens.push(c.to_string()),
+            '(' => {
+                depth += 1;
+                word.push(c);
+            }
+            ')' if depth > 0 => {
+                depth -= 1;
+                word.push(c);
+            }
+            ')' => {
+                if !word.is_empty() {
+                    tokens.push(std::mem::take(&mut word));
+                }
+                tokens.push(c.to_string());
+            }
+            c if c.is_whitespace() => {
+                if !word.is_empty() {
+                    tokens.push(std::mem::take(&mut word));
+                }
+                depth = 0;
+            }
+            c => word.push(c),
+        }
+    }
+    if quoted {
+        return Err(invalid(String::from("unclosed quote")));
+    }
+    if !word.is_empty() {
+        tokens.push(word);
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser, one method per precedence level.
+struct Parser {
+    tokens: Peekable<IntoIter<String>>,
+    today: NaiveDate,
+}
+
+impl Parser {
+    fn or(&mut self) -> Result<Filter> {
+        let mut filter = self.and()?;
+        while self.tokens.next_if(|t| t == "or").is_some() {
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<Filter> {
+        let mut filter = self.not()?;
+        loop {
+            if self.tokens.next_if(|t| t == "and").is_none()
+                && !self.tokens.peek().is_some_and(|t| t != "or" && t != ")")
+            {
+                return Ok(filter);
+            }
+            filter = Filter::And(Box::new(filter), Box::new(self.not()?));
+        }
+    }
+
+    fn not(&mut self) -> Result<Filter> {
+        if self.tokens.next_if(|t| t == "not").is_some() {
+            return Ok(Filter::Not(Box::new(self.not()?)));
+        }
+        match self.tokens.next().as_deref() {
+            Some("(") => {
+                let filter = self.or()?;
+                match self.tokens.next().as_deref() {
+                    Some(")") => Ok(filter),
+                    _ => Err(invalid(String::from("missing \")\""))),
+                }
+            }
+            Some(")") => Err(invalid(String::from("unexpected \")\""))),
+            Some(term) => self.term(term),
+            None => Err(invalid(String::from("the filter ends early"))),
+        }
+    }
+
+    fn term(&self, term: &str) -> Result<Filter> {
+        if let Some(tag) = term.strip_prefix('+') {
+            return Ok(Filter::Tag(tag.to_owned()));
+        }
+        if let Some(tag) = term.strip_prefix('-') {
+            return Ok(Filter::Not(Box::new(Filter::Tag(tag.to_owned()))));
+        }
+        if let Some((field, pattern)) = term.split_once('~') {
+            let regex =
+                Regex::new(pattern).map_err(|_| invalid(format!("bad regex \"{}\"", pattern)))?;
+            return match field {
+                "name" => Ok(Filter::Name(regex)),
+                "desc" | "description" => Ok(Filter::Desc(regex)),
+                _ => Err(invalid(format!(
+                    "can't match \"{}\" against a regex",
+                    field
+                ))),
+            };
+        }
+        let (field, value) = term
+            .split_once(':')
+            .ok_or_else(|| invalid(format!("\"{}\" is not a filter term", term)))?;
+        match field {
+            "status" => Ok(Filter::Status(value.parse()?)),
+            "project" if value == "none" => Ok(Filter::Project(None)),
+            "project" => Ok(Filter::Project(Some(value.to_owned()))),
+            "tag" => Ok(Filter::Tag(value.to_owned())),
+            "priority" if value == "none" => Ok(Filter::Priority(None)),
+            "priority" => Ok(Filter::Priority(Some(value.parse()?))),
+            "due" if value == "none" => Ok(Filter::Due(None)),
+            "due" => Ok(Filter::Due(Some(parse_date(value, self.today)?))),
+            "due.before" => Ok(Filter::DueBefore(parse_date(value, self.today)?)),
+            "due.after" => Ok(Filter::DueAfter(parse_date(value, self.today)?)),
+            _ => Err(invalid(format!("unknown field \"{}\"", field))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{
+        handler::{TaskEdit, TaskHandler, Timestamps},
+        store::MemoryStore,
+    };
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()
+    }
+
+    fn task(name: &str, tags: &[&str]) -> Task {
+        let now = Utc::now();
+        Task {
+            id: 1,
+            uuid: Uuid::nil(),
+            name: name.to_owned(),
+            project: None,
+            parent: None,
+            desc: String::new(),
+            status: Status::Active,
+            due: None,
+            priority: None,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            depends: Vec::new(),
+            blocked: false,
+            recur: None,
+            archived: false,
+            overdue: false,
+            times: Timestamps {
+                created_at: now,
+                modified_at: now,
+                started_at: None,
+                completed_at: None,
+                canceled_at: None,
+            },
+            intervals: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    fn matches(expr: &str, task: &Task) -> bool {
+        Filter::parse(expr, today()).unwrap().matches(task)
+    }
+
+    #[test]
+    fn splits_words_and_parentheses() {
+        assert_eq!(
+            tokenize("name~^(wr|re) and (status:active)").unwrap(),
+            ["name~^(wr|re)", "and", "(", "status:active", ")"]
+        );
+        assert_eq!(
+            tokenize("desc~\"call back\" +phone").unwrap(),
+            ["desc~call back", "+phone"]
+        );
+        assert!(tokenize("desc~\"call back").is_err());
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let filter = "+a or +b +c";
+        assert!(matches(filter, &task("x", &["a"])));
+        assert!(!matches(filter, &task("x", &["b"])));
+        assert!(matches(filter, &task("x", &["b", "c"])));
+
+        let grouped = "(+a or +b) +c";
+        assert!(!matches(grouped, &task("x", &["a"])));
+        assert!(matches(grouped, &task("x", &["a", "c"])));
+    }
+
+    #[test]
+    fn implicit_and_is_the_same_as_explicit() {
+        for tags in [&[][..], &["a"], &["b"], &["a", "b"]] {
+            let task = task("x", tags);
+            assert_eq!(matches("+a +b", &task), matches("+a and +b", &task));
+        }
+    }
+
+    #[test]
+    fn not_applies_to_the_next_term() {
+        assert!(matches("not +a or +b", &task("x", &["a", "b"])));
+        assert!(!matches("not +a or +b", &task("x", &["a"])));
+        assert!(matches("not (+a or +b)", &task("x", &[])));
+        assert!(matches("-a", &task("x", &["b"])));
+        assert!(!matches("-a", &task("x", &["a"])));
+    }
+
+    #[test]
+    fn regexes_keep_their_parentheses() {
+        assert!(matches("name~^(wr|re)", &task("write", &[])));
+        assert!(matches("(name~^(wr|re))", &task("read", &[])));
+        assert!(!matches("name~^(wr|re)", &task("plan", &[])));
+        assert!(matches("desc~\"^$\"", &task("plan", &[])));
+    }
+
+    #[test]
+    fn rejects_invalid_filters() {
+        for expr in [
+            "",
+            "(+a",
+            "+a)",
+            "and",
+            "+a or",
+            "a",
+            "status:bogus",
+            "size:3",
+            "name~(",
+            "due:someday",
+        ] {
+            assert!(
+                Filter::parse(expr, today()).is_err(),
+                "{:?} was accepted",
+                expr
+            );
+        }
+    }
+
+    #[test]
+    fn query_narrows_by_required_terms() {
+        let query = Filter::parse(
+            "(status:active or status:stopped) +x due.before:fri",
+            today(),
+        )
+        .unwrap()
+        .query();
+        assert_eq!(query.statuses, [Status::Active, Status::Stopped]);
+        assert_eq!(query.tags, ["x"]);
+        assert_eq!(query.due_before, NaiveDate::from_ymd_opt(2024, 3, 8));
+
+        let query = Filter::parse("+a or +b", today()).unwrap().query();
+        assert!(query.tags.is_empty());
+    }
+
+    #[test]
+    fn query_selects_every_match() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+        let store = Box::new(MemoryStore::default());
+        let mut handler = TaskHandler::new(store, Box::new(move || now)).unwrap();
+        let tasks: [(&str, Status, &[&str], u32); 5] = [
+            ("write", Status::Active, &["a"], 1),
+            ("read", Status::Stopped, &["a", "b"], 5),
+            ("plan", Status::Completed, &["b"], 9),
+            ("call", Status::Canceled, &[], 2),
+            ("test", Status::Active, &["b", "c"], 20),
+        ];
+        for (name, status, tags, day) in tasks {
+            let id = handler.create_task(name, None).unwrap();
+            let edit = TaskEdit {
+                status: Some(status),
+                add_tags: tags.to_vec(),
+                due: Some(NaiveDate::from_ymd_opt(2024, 3, day)),
+                ..Default::default()
+            };
+            handler.edit_task(id, edit).unwrap();
+        }
+
+        for expr in [
+            "+a",
+            "+a +b",
+            "+a or +b",
+            "-a",
+            "not +b status:active",
+            "status:active or status:stopped",
+            "status:active or +b",
+            "(status:completed or status:canceled) due.before:+7d",
+            "due.after:2024-03-04 due.after:2024-03-01 due.before:2024-03-10",
+            "due.before:2024-03-06 or due.after:2024-03-08",
+            "not (status:active or status:stopped)",
+        ] {
+            let filter = Filter::parse(expr, today()).unwrap();
+            let ids = |tasks: Vec<Task>| -> Vec<u32> {
+                tasks
+                    .iter()
+                    .filter(|task| filter.matches(task))
+                    .map(|task| task.id)
+                    .collect()
+            };
+            let narrowed = ids(handler.query_content(&filter.query()).unwrap());
+            assert_eq!(narrowed, ids(handler.all_content().unwrap()), "{}", expr);
+        }
+    }
+}
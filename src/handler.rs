@@ -1,190 +1,1505 @@
-use crate::output::TaskContent;
+use crate::clock::Clock;
+use crate::recur::Recurrence;
 use crate::result::*;
+use crate::store::{self, Backup, Query, Snapshot, StoreMeta, TaskStore};
+use crate::task::{Priority, Status, Task};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use serde_json::Value;
 use std::{
-    collections::HashMap,
-    fs::File,
-    io::BufReader,
-    path::{Path, PathBuf},
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+    time::Duration,
 };
+use uuid::Uuid;
 
-const SAVE_FILE_NAME: &str = "task.json";
+/// Credited in the change history when no user is set.
+const UNKNOWN_USER: &str = "unknown";
+
+/// How many runs `undo` can step back through.
+const JOURNAL_LENGTH: usize = 100;
+
+/// Separates the project from the name in a qualified task name.
+pub const PROJECT_SEPARATOR: char = '/';
+
+/// Separates several tasks given as one argument, so names can't contain it.
+pub const LIST_SEPARATOR: char = ',';
 
 pub struct TaskHandler {
     data: TaskData,
-    path: PathBuf,
+    clock: Box<dyn Clock>,
+    /// Who the change history credits with this run's changes.
+    user: String,
+    /// Status new tasks start in.
+    default_status: Status,
 }
 
 impl TaskHandler {
-    pub fn from_json(path: &Path) -> Result<Self> {
-        let mut save_path = path.to_path_buf();
-        save_path.push(SAVE_FILE_NAME);
+    /// Works on the tasks kept in `store`.
+    pub fn new(store: Box<dyn TaskStore>, clock: Box<dyn Clock>) -> Result<Self> {
+        Ok(Self {
+            data: TaskData::new(store)?,
+            clock,
+            user: String::from(UNKNOWN_USER),
+            default_status: Status::default(),
+        })
+    }
+
+    /// Opens the store at `path`, see [`store::open`].
+    pub fn open(path: &Path, clock: Box<dyn Clock>, lock_timeout: Duration) -> Result<Self> {
+        let store = store::open(path, lock_timeout, clock.now())?;
+        Self::new(store, clock)
+    }
 
-        match get_save(path) {
-            Some(save) => {
-                let rdr = BufReader::new(save);
-                let data = serde_json::from_reader(rdr)?;
+    /// Hands the changes made so far to the store and saves it.
+    pub fn save(&mut self) -> Result<()> {
+        self.data.save()
+    }
 
-                Ok(Self {
-                    data,
-                    path: save_path,
-                })
-            }
-            None => {
-                create_save(&save_path)?;
-                Ok(Self {
-                    data: TaskData::default(),
-                    path: save_path,
-                })
+    /// The backups of earlier saves, newest first.
+    pub fn backups(&self) -> Vec<Backup> {
+        self.data.store.backups()
+    }
+
+    /// Replaces the tasks and the undo history with those in a backup. The
+    /// current tasks become the newest backup when saving, so a restore can
+    /// be rolled back too. The change history is kept, every task the
+    /// restore changes gets a `restored` entry, and short IDs handed out
+    /// since the backup aren't reused.
+    pub fn restore(&mut self, number: usize) -> Result<Message> {
+        let backup = self.data.store.read_backup(number)?;
+        let current = self.data.store.snapshot()?;
+        let mut history = current.history;
+        for (uuid, entries) in std::mem::take(&mut self.data.history) {
+            history.entry(uuid).or_default().extend(entries);
+        }
+
+        let saved = by_uuid(&current.tasks);
+        let restored = by_uuid(&backup.tasks);
+        let entry = HistoryEntry {
+            at: self.clock.now(),
+            user: self.user.clone(),
+            field: String::from("restored"),
+            old: Value::Null,
+            new: Value::from(format!("backup {}", number)),
+        };
+        let uuids: BTreeSet<Uuid> = saved.keys().chain(restored.keys()).copied().collect();
+        for uuid in uuids {
+            if saved.get(&uuid) != restored.get(&uuid) {
+                history.entry(uuid).or_default().push(entry.clone());
             }
         }
+
+        self.data.replace(Snapshot {
+            next_id: backup.next_id.max(current.next_id),
+            history,
+            ..backup
+        })?;
+        Ok(Message::Restored(number))
     }
 
-    pub fn save(&mut self) -> Result<()> {
-        let serialized = serde_json::to_string_pretty(&self.data)?;
-        std::fs::write(&self.path, serialized)?;
+    /// Records the changes made during this run in the journal, so `undo`
+    /// can revert them. Making new changes drops the redo history.
+    pub fn commit(&mut self, command: &str) -> Result<()> {
+        let touched = std::mem::take(&mut self.data.touched);
+        let mut changes = Vec::new();
+        for (id, before) in touched {
+            let after = self.data.get_task(id)?;
+            if before != after {
+                changes.push(Change { id, before, after });
+            }
+        }
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let now = self.clock.now();
+        for change in &changes {
+            self.record_history(change, false, now);
+        }
+        let journal = &mut self.data.meta.journal;
+        journal.redo.clear();
+        journal.undo.push(Transaction {
+            command: command.to_owned(),
+            at: now,
+            changes,
+        });
+        if journal.undo.len() > JOURNAL_LENGTH {
+            journal.undo.remove(0);
+        }
         Ok(())
     }
 
-    pub fn create_task(&mut self, name: &str) -> Result<Message> {
-        if !self.task_exists(name) {
-            self.data.new_task(name, None);
-            return Ok(Message::CreatedTask(name.to_owned()));
+    /// Reverts the changes of the latest run that wasn't undone yet.
+    pub fn undo(&mut self) -> Result<Message> {
+        let transaction = self
+            .data
+            .meta
+            .journal
+            .undo
+            .pop()
+            .ok_or(SystemError::NothingToUndo)?;
+        self.data.apply(&transaction.changes, true);
+        let now = self.clock.now();
+        for change in &transaction.changes {
+            self.record_history(change, true, now);
         }
-        Err(SystemError::TaskAlreadyExists(name.to_owned()).into())
+        let message = Message::Undone(transaction.command.clone(), transaction.changes.len());
+        self.data.meta.journal.redo.push(transaction);
+        Ok(message)
     }
 
-    pub fn delete_task(&mut self, name: &str) -> Result<()> {
-        if self.task_exists(name) {
-            self.data.delete_task(name);
-            return Ok(());
+    /// Applies the changes of the latest undone run again.
+    pub fn redo(&mut self) -> Result<Message> {
+        let transaction = self
+            .data
+            .meta
+            .journal
+            .redo
+            .pop()
+            .ok_or(SystemError::NothingToRedo)?;
+        self.data.apply(&transaction.changes, false);
+        let now = self.clock.now();
+        for change in &transaction.changes {
+            self.record_history(change, false, now);
         }
-        Err(SystemError::TaskDoesntExist(name.to_owned()).into())
+        let message = Message::Redone(transaction.command.clone(), transaction.changes.len());
+        self.data.meta.journal.undo.push(transaction);
+        Ok(message)
     }
 
-    pub fn edit_task(
-        &mut self,
-        name: &str,
-        desc: Option<&str>,
-        status: Option<&str>,
-        new_name: Option<&str>,
-    ) -> Result<Message> {
-        if let Some(properties) = self.data.get_mut_task(name) {
-            if let Some(description) = desc {
-                properties.desc = description.to_owned();
+    /// Sets the status new tasks start in.
+    pub fn set_default_status(&mut self, status: Status) {
+        self.default_status = status;
+    }
+
+    /// Sets who the change history credits with this run's changes.
+    pub fn set_user(&mut self, user: &str) {
+        self.user = user.to_owned();
+    }
+
+    /// Appends the fields a change touched to the task's history, `reverse`
+    /// records the change being undone.
+    fn record_history(&mut self, change: &Change, reverse: bool, now: DateTime<Utc>) {
+        let (before, after) = if reverse {
+            (&change.after, &change.before)
+        } else {
+            (&change.before, &change.after)
+        };
+        let Some(uuid) = before.as_ref().or(after.as_ref()).map(|t| t.uuid) else {
+            return;
+        };
+        let entry = |field: &str, old: Value, new: Value| HistoryEntry {
+            at: now,
+            user: self.user.clone(),
+            field: field.to_owned(),
+            old,
+            new,
+        };
+        let entries = match (before, after) {
+            (None, Some(task)) => vec![entry(
+                "created",
+                Value::Null,
+                Value::from(task.name.clone()),
+            )],
+            (Some(task), None) => vec![entry(
+                "deleted",
+                Value::from(task.name.clone()),
+                Value::Null,
+            )],
+            (Some(before), Some(after)) => {
+                let before = fields(before);
+                let after = fields(after);
+                let mut keys: BTreeSet<&String> = before.keys().collect();
+                keys.extend(after.keys());
+                keys.into_iter()
+                    .filter(|key| key.as_str() != "modified_at")
+                    .filter(|key| before.get(*key) != after.get(*key))
+                    .map(|key| {
+                        let old = before.get(key).cloned().unwrap_or_default();
+                        let new = after.get(key).cloned().unwrap_or_default();
+                        entry(key, old, new)
+                    })
+                    .collect()
+            }
+            (None, None) => Vec::new(),
+        };
+        self.data.history.entry(uuid).or_default().extend(entries);
+    }
+
+    /// The change history of a task, oldest first. Deleted tasks can still
+    /// be looked up by their UUID.
+    pub fn history(&self, key: &str) -> Result<Vec<HistoryEntry>> {
+        let uuid = match self.find_task(key) {
+            Ok(id) => self.data.get_task(id)?.unwrap().uuid,
+            Err(e) => match key.parse::<Uuid>() {
+                Ok(uuid) if !self.data.history(uuid)?.is_empty() => uuid,
+                _ => return Err(e),
+            },
+        };
+        self.data.history(uuid)
+    }
+
+    /// Every task with its ID and change history, as pretty printed JSON.
+    pub fn export_json(&self) -> Result<String> {
+        let mut tasks = Vec::new();
+        for (id, task) in self.data.query(&Query::default())? {
+            let mut value = serde_json::to_value(&task)?;
+            value["id"] = Value::from(id);
+            let history = self.data.history(task.uuid)?;
+            value["history"] = if history.is_empty() {
+                Value::Null
+            } else {
+                serde_json::to_value(history)?
             };
-            if let Some(s) = status {
-                properties.status = s.to_owned();
+            tasks.push(value);
+        }
+        Ok(serde_json::to_string_pretty(&tasks)?)
+    }
+
+    /// Creates a new task in `project` and returns its short ID.
+    pub fn create_task(&mut self, name: &str, project: Option<&str>) -> Result<u32> {
+        validate_name(name)?;
+        if let Some(project) = project {
+            validate_project(project)?;
+        }
+        if !self.task_exists(project, name)? {
+            let id = self
+                .data
+                .new_task(name, self.default_status, self.clock.now());
+            self.data.get_mut_task(id)?.unwrap().project = project.map(str::to_owned);
+            return Ok(id);
+        }
+        Err(SystemError::TaskAlreadyExists(qualified_name(project, name)).into())
+    }
+
+    /// Deletes a task and returns its name. Its subtasks move up to the
+    /// task's own parent.
+    pub fn delete_task(&mut self, id: u32) -> Result<String> {
+        match self.data.delete_task(id)? {
+            Some(properties) => {
+                for child in self.data.children(id)? {
+                    self.data.get_mut_task(child)?.unwrap().parent = properties.parent;
+                }
+                let dependents = self.data.query(&Query {
+                    depends_on: Some(id),
+                    ..Default::default()
+                })?;
+                for (dependent, _) in dependents {
+                    self.data
+                        .get_mut_task(dependent)?
+                        .unwrap()
+                        .depends
+                        .remove(&id);
+                }
+                Ok(properties.name)
             }
-            if let Some(new_name) = new_name {
-                if self.task_exists(new_name) {
-                    return Err(SystemError::TaskAlreadyExists(new_name.to_owned()).into());
+            None => Err(SystemError::TaskDoesntExist(id.to_string()).into()),
+        }
+    }
+
+    pub fn edit_task(&mut self, id: u32, edit: TaskEdit) -> Result<Message> {
+        for tag in edit.add_tags.iter().chain(&edit.remove_tags) {
+            validate_tag(tag)?;
+        }
+        if let Some(new_name) = edit.name {
+            validate_name(new_name)?;
+        }
+        if let Some(Some(project)) = edit.project {
+            validate_project(project)?;
+        }
+        if let Some(task) = self.data.get_task(id)? {
+            let name = edit.name.unwrap_or(&task.name);
+            let project = edit.project.unwrap_or(task.project.as_deref());
+            if self
+                .data
+                .find(project, name)?
+                .is_some_and(|other| other != id)
+            {
+                return Err(SystemError::TaskAlreadyExists(qualified_name(project, name)).into());
+            }
+            // Fail before changing anything if the next instance can't be dated.
+            let recur = edit
+                .recur
+                .as_ref()
+                .map_or(task.recur.as_ref(), Option::as_ref);
+            if let Some(recur) = recur {
+                if completes(&edit, task.status) {
+                    recur.next_due(edit.due.unwrap_or(task.due), self.today())?;
                 }
-                let properties = self.data.delete_task(name);
-                self.data.new_task(new_name, Some(properties));
             }
-            Ok(Message::AppliedTaskChanges(name.to_string()))
+        }
+        if let Some(Some(parent)) = edit.parent {
+            self.check_parent(id, parent)?;
+        }
+        for dependency in &edit.add_depends {
+            self.check_dependency(id, *dependency)?;
+        }
+        let now = self.clock.now();
+        let changed = !edit.is_empty();
+        if edit.status == Some(Status::Completed) {
+            self.complete_subtasks(id, edit.cascade, now)?;
+        }
+        if let Some(properties) = self.data.get_mut_task(id)? {
+            let name = properties.name.clone();
+            let recurs = completes(&edit, properties.status);
+            if let Some(description) = edit.desc {
+                properties.desc = description.to_owned();
+            };
+            if let Some(s) = edit.status {
+                properties.set_status(s, now);
+            }
+            if let Some(new_name) = edit.name {
+                properties.name = new_name.to_owned();
+            }
+            if let Some(project) = edit.project {
+                properties.project = project.map(str::to_owned);
+            }
+            if let Some(parent) = edit.parent {
+                properties.parent = parent;
+            }
+            if let Some(due) = edit.due {
+                properties.due = due;
+            }
+            if let Some(priority) = edit.priority {
+                properties.priority = priority;
+            }
+            for tag in &edit.add_tags {
+                properties.tags.insert(tag.to_string());
+            }
+            for tag in &edit.remove_tags {
+                properties.tags.remove(*tag);
+            }
+            properties.depends.extend(&edit.add_depends);
+            for dependency in &edit.remove_depends {
+                properties.depends.remove(dependency);
+            }
+            if let Some(recur) = edit.recur {
+                properties.recur = recur;
+            }
+            if changed {
+                properties.times.modified_at = now;
+            }
+            if recurs && properties.recur.is_some() {
+                let (next, due) = self.recur(id, now)?;
+                return Ok(Message::Recurred(name, next, due));
+            }
+            Ok(Message::AppliedTaskChanges(name))
         } else {
-            Err(SystemError::TaskDoesntExist(name.to_owned()).into())
+            Err(SystemError::TaskDoesntExist(id.to_string()).into())
         }
     }
 
-    pub fn task_exists(&mut self, name: &str) -> bool {
-        self.data.task_exists(name)
+    /// Appends a timestamped note to a task.
+    pub fn annotate(&mut self, id: u32, text: &str) -> Result<Message> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(SystemError::EmptyNote.into());
+        }
+        let now = self.clock.now();
+        let task = self
+            .data
+            .get_mut_task(id)?
+            .ok_or_else(|| SystemError::TaskDoesntExist(id.to_string()))?;
+        task.notes.push(Note {
+            entry: now,
+            text: text.to_owned(),
+        });
+        task.times.modified_at = now;
+        Ok(Message::Annotated(task.name.clone()))
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.data.tasks.is_empty()
+    /// Removes a task's note by its 1-based position, as shown by `--info`.
+    pub fn denotate(&mut self, id: u32, number: usize) -> Result<Message> {
+        let now = self.clock.now();
+        let task = self
+            .data
+            .get_mut_task(id)?
+            .ok_or_else(|| SystemError::TaskDoesntExist(id.to_string()))?;
+        if number == 0 || number > task.notes.len() {
+            return Err(SystemError::NoteDoesntExist(task.name.clone(), number).into());
+        }
+        task.notes.remove(number - 1);
+        task.times.modified_at = now;
+        Ok(Message::Denotated(task.name.clone(), number))
+    }
+
+    /// Archives a completed recurring task and creates its next instance,
+    /// returning the new short ID and due date. The next instance isn't
+    /// tracked until it's set active.
+    fn recur(&mut self, id: u32, now: DateTime<Utc>) -> Result<(u32, NaiveDate)> {
+        let today = self.today();
+        let task = self.data.get_mut_task(id)?.unwrap();
+        let recur = task.recur.clone().unwrap();
+        let due = recur.next_due(task.due, today)?;
+        task.archived = true;
+        task.recur = None;
+
+        let mut next = TaskProperties::new(&task.name, self.default_status, now);
+        next.project = task.project.clone();
+        next.parent = task.parent;
+        next.desc = task.desc.clone();
+        next.priority = task.priority;
+        next.tags = task.tags.clone();
+        next.depends = task.depends.clone();
+        next.due = Some(due);
+        next.recur = Some(recur);
+        Ok((self.data.insert_task(next), due))
+    }
+
+    /// A task can't become a subtask of itself or of one of its subtasks.
+    fn check_parent(&self, id: u32, parent: u32) -> Result<()> {
+        if self.data.get_task(parent)?.is_none() {
+            return Err(SystemError::TaskDoesntExist(parent.to_string()).into());
+        }
+        if parent == id || self.data.descendants(id)?.contains(&parent) {
+            let name = self.data.get_task(id)?.map_or(id.to_string(), |t| t.name);
+            return Err(SystemError::ParentCycle(name).into());
+        }
+        Ok(())
+    }
+
+    /// Rejects dependencies that would make a task wait on itself.
+    fn check_dependency(&self, id: u32, dependency: u32) -> Result<()> {
+        if self.data.get_task(dependency)?.is_none() {
+            return Err(SystemError::TaskDoesntExist(dependency.to_string()).into());
+        }
+        if dependency == id || self.data.all_dependencies(dependency)?.contains(&id) {
+            let name = |id: u32| -> Result<String> {
+                Ok(self.data.get_task(id)?.map_or(id.to_string(), |t| t.name))
+            };
+            return Err(SystemError::DependencyCycle(name(id)?, name(dependency)?).into());
+        }
+        Ok(())
+    }
+
+    /// Completing a task with open subtasks fails, unless `cascade` is set
+    /// in which case the subtasks are completed as well. Recurring subtasks
+    /// are archived and get their next instance, as if completed by hand.
+    fn complete_subtasks(&mut self, id: u32, cascade: bool, now: DateTime<Utc>) -> Result<()> {
+        let mut open = Vec::new();
+        for child in self.data.descendants(id)? {
+            if self.data.get_task(child)?.unwrap().is_open() {
+                open.push(child);
+            }
+        }
+        if open.is_empty() {
+            return Ok(());
+        }
+        if !cascade {
+            let name = self.data.get_task(id)?.unwrap().name;
+            return Err(SystemError::OpenSubtasks(name, open.len()).into());
+        }
+        for &child in &open {
+            let task = self.data.get_task(child)?.unwrap();
+            if let Some(recur) = &task.recur {
+                recur.next_due(task.due, self.today())?;
+            }
+        }
+        for child in open {
+            let task = self.data.get_mut_task(child)?.unwrap();
+            task.set_status(Status::Completed, now);
+            task.times.modified_at = now;
+            if task.recur.is_some() {
+                self.recur(child, now)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The current time according to the handler's clock.
+    pub fn now(&self) -> DateTime<Utc> {
+        self.clock.now()
+    }
+
+    /// The current date in the local timezone, according to the handler's clock.
+    pub fn today(&self) -> NaiveDate {
+        self.clock.now().with_timezone(&Local).date_naive()
+    }
+
+    /// Resolves a short ID, UUID or task name to the task's short ID.
+    ///
+    /// Names can be qualified with their project as `project/name`, a bare
+    /// name only resolves if a single project has a task with that name.
+    pub fn find_task(&self, key: &str) -> Result<u32> {
+        let found = if let Ok(id) = key.parse::<u32>() {
+            self.data.get_task(id)?.map(|_| id)
+        } else if let Ok(uuid) = key.parse::<Uuid>() {
+            self.data.find_by_uuid(uuid)?
+        } else if let Some((project, name)) = key.rsplit_once(PROJECT_SEPARATOR) {
+            self.data.find(Some(project), name)?
+        } else {
+            let found = self.data.find_by_name(key)?;
+            if found.len() > 1 {
+                return Err(SystemError::AmbiguousTask(key.to_owned()).into());
+            }
+            found.first().copied()
+        };
+        found.ok_or_else(|| SystemError::TaskDoesntExist(key.to_owned()).into())
+    }
+
+    pub fn task_exists(&self, project: Option<&str>, name: &str) -> Result<bool> {
+        Ok(self.data.find(project, name)?.is_some())
     }
 
-    pub fn get_content(&self, name: &str) -> Result<TaskContent> {
-        if let Some(task) = self.data.get_task(name) {
-            return Ok(TaskContent::new(
-                name,
-                &task.desc,
-                task.status.as_str().into(),
-            ));
+    pub fn is_empty(&self) -> Result<bool> {
+        let query = Query {
+            limit: Some(1),
+            ..Default::default()
         };
-        Err(SystemError::TaskDoesntExist(name.to_owned()).into())
+        Ok(self.data.query(&query)?.is_empty())
     }
 
-    pub fn all_content(&self) -> Vec<TaskContent> {
+    pub fn get_content(&self, id: u32) -> Result<Task> {
+        match self.data.get_task(id)? {
+            Some(task) => self.content(id, &task, self.today()),
+            None => Err(SystemError::TaskDoesntExist(id.to_string()).into()),
+        }
+    }
+
+    /// Content of the direct subtasks of a task.
+    pub fn subtasks(&self, id: u32) -> Result<Vec<Task>> {
+        self.query_content(&Query {
+            parent: Some(id),
+            ..Default::default()
+        })
+    }
+
+    /// Open tasks that aren't waiting on any dependency.
+    pub fn ready_content(&self) -> Result<Vec<Task>> {
+        let open = self.query_content(&Query {
+            statuses: vec![Status::Active, Status::Stopped],
+            ..Default::default()
+        })?;
+        Ok(open.into_iter().filter(|t| !t.blocked).collect())
+    }
+
+    pub fn all_content(&self) -> Result<Vec<Task>> {
+        self.query_content(&Query::default())
+    }
+
+    /// Content of the tasks matching `query`, ordered by ID.
+    pub fn query_content(&self, query: &Query) -> Result<Vec<Task>> {
+        let today = self.today();
         let mut content = Vec::new();
-        for (name, p) in &self.data.tasks {
-            let task = TaskContent::new(name, &p.desc, p.status.as_str().into());
-            content.push(task);
+        for (id, task) in self.data.query(query)? {
+            content.push(self.content(id, &task, today)?);
         }
-        content
+        Ok(content)
     }
+
+    fn content(&self, id: u32, task: &TaskProperties, today: NaiveDate) -> Result<Task> {
+        let mut content = task.content(id, today);
+        for dependency in &task.depends {
+            if self
+                .data
+                .get_task(*dependency)?
+                .is_some_and(|d| d.status != Status::Completed)
+            {
+                content.blocked = true;
+                break;
+            }
+        }
+        Ok(content)
+    }
+}
+
+/// Changes to apply to a task, `None` fields are left untouched.
+#[derive(Default, Clone)]
+pub struct TaskEdit<'a> {
+    pub desc: Option<&'a str>,
+    pub status: Option<Status>,
+    pub name: Option<&'a str>,
+    /// `Some(None)` moves the task out of its project.
+    pub project: Option<Option<&'a str>>,
+    /// `Some(None)` makes the task a top level task.
+    pub parent: Option<Option<u32>>,
+    /// `Some(None)` removes the due date.
+    pub due: Option<Option<NaiveDate>>,
+    /// `Some(None)` removes the priority.
+    pub priority: Option<Option<Priority>>,
+    pub add_tags: Vec<&'a str>,
+    pub remove_tags: Vec<&'a str>,
+    pub add_depends: Vec<u32>,
+    pub remove_depends: Vec<u32>,
+    /// `Some(None)` stops the task from recurring.
+    pub recur: Option<Option<Recurrence>>,
+    /// Completing the task also completes its open subtasks instead of failing.
+    pub cascade: bool,
 }
 
-#[derive(serde::Deserialize, serde::Serialize, Debug, Default)]
+impl TaskEdit<'_> {
+    fn is_empty(&self) -> bool {
+        self.desc.is_none()
+            && self.status.is_none()
+            && self.name.is_none()
+            && self.project.is_none()
+            && self.parent.is_none()
+            && self.due.is_none()
+            && self.priority.is_none()
+            && self.add_tags.is_empty()
+            && self.remove_tags.is_empty()
+            && self.add_depends.is_empty()
+            && self.remove_depends.is_empty()
+            && self.recur.is_none()
+    }
+}
+
+/// The handler's view of the store. Tasks changed during this run are
+/// kept here until they are handed to the store when saving.
 struct TaskData {
-    tasks: HashMap<String, TaskProperties>,
+    store: Box<dyn TaskStore>,
+    meta: StoreMeta,
+    /// Tasks changed since the last save, `None` for deleted ones.
+    changed: BTreeMap<u32, Option<TaskProperties>>,
+    /// Tasks changed during this run, as they were before the first change.
+    touched: BTreeMap<u32, Option<TaskProperties>>,
+    /// History entries recorded since the last save.
+    history: BTreeMap<Uuid, Vec<HistoryEntry>>,
+}
+
+/// One field of a task changing, `created` and `deleted` entries mark the
+/// task appearing and disappearing.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub at: DateTime<Utc>,
+    pub user: String,
+    pub field: String,
+    /// `null` when the field wasn't set.
+    pub old: Value,
+    pub new: Value,
+}
+
+/// Tasks keyed by their UUID, which unlike short IDs is kept across saves.
+fn by_uuid(tasks: &BTreeMap<u32, TaskProperties>) -> BTreeMap<Uuid, &TaskProperties> {
+    tasks.values().map(|task| (task.uuid, task)).collect()
+}
+
+/// A task's fields as they are saved, for comparing versions of it.
+fn fields(task: &TaskProperties) -> serde_json::Map<String, Value> {
+    match serde_json::to_value(task) {
+        Ok(Value::Object(fields)) => fields,
+        _ => serde_json::Map::new(),
+    }
+}
+
+/// Changes made by earlier runs, kept so they can be undone and redone.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Default, Clone, PartialEq)]
+pub struct Journal {
+    #[serde(default)]
+    undo: Vec<Transaction>,
+    #[serde(default)]
+    redo: Vec<Transaction>,
+}
+
+impl Journal {
+    pub fn is_empty(&self) -> bool {
+        self.undo.is_empty() && self.redo.is_empty()
+    }
+}
+
+/// Everything one run of the app changed, undone and redone as a whole.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
+struct Transaction {
+    /// The command line that made the changes.
+    command: String,
+    at: DateTime<Utc>,
+    changes: Vec<Change>,
+}
+
+/// A task before and after a transaction, `None` when it didn't exist.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
+struct Change {
+    id: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    before: Option<TaskProperties>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    after: Option<TaskProperties>,
 }
 
 impl TaskData {
-    fn new_task(&mut self, name: &str, properties: Option<TaskProperties>) {
-        if let Some(properties) = properties {
-            self.tasks.insert(name.to_owned(), properties);
+    fn new(mut store: Box<dyn TaskStore>) -> Result<Self> {
+        Ok(Self {
+            meta: store.load()?,
+            store,
+            changed: BTreeMap::new(),
+            touched: BTreeMap::new(),
+            history: BTreeMap::new(),
+        })
+    }
+
+    /// Hands every change since the last save to the store and saves it.
+    fn save(&mut self) -> Result<()> {
+        for (id, task) in std::mem::take(&mut self.changed) {
+            match (self.store.get(id)?, task) {
+                (None, Some(task)) => self.store.insert(id, &task)?,
+                (Some(saved), Some(task)) if saved != task => self.store.update(id, &task)?,
+                (Some(_), None) => self.store.delete(id)?,
+                _ => (),
+            }
+        }
+        for (uuid, entries) in std::mem::take(&mut self.history) {
+            self.store.add_history(uuid, &entries)?;
+        }
+        self.store.save(&self.meta)
+    }
+
+    /// Replaces everything in the store, dropping unsaved changes.
+    fn replace(&mut self, snapshot: Snapshot) -> Result<()> {
+        self.store.replace(snapshot)?;
+        self.meta = self.store.load()?;
+        self.changed.clear();
+        self.touched.clear();
+        self.history.clear();
+        Ok(())
+    }
+
+    fn new_task(&mut self, name: &str, status: Status, now: DateTime<Utc>) -> u32 {
+        self.insert_task(TaskProperties::new(name, status, now))
+    }
+
+    fn insert_task(&mut self, properties: TaskProperties) -> u32 {
+        let id = self.meta.next_id;
+        self.meta.next_id += 1;
+        self.touched.entry(id).or_insert(None);
+        self.changed.insert(id, Some(properties));
+        id
+    }
+
+    fn delete_task(&mut self, id: u32) -> Result<Option<TaskProperties>> {
+        self.touch(id)?;
+        let task = self.get_task(id)?;
+        self.changed.insert(id, None);
+        Ok(task)
+    }
+
+    fn get_mut_task(&mut self, id: u32) -> Result<Option<&mut TaskProperties>> {
+        self.touch(id)?;
+        if !self.changed.contains_key(&id) {
+            let task = self.store.get(id)?;
+            self.changed.insert(id, task);
+        }
+        Ok(self.changed.get_mut(&id).unwrap().as_mut())
+    }
+
+    /// Remembers how a task looked before this run first changed it.
+    fn touch(&mut self, id: u32) -> Result<()> {
+        if !self.touched.contains_key(&id) {
+            let task = self.get_task(id)?;
+            self.touched.insert(id, task);
+        }
+        Ok(())
+    }
+
+    /// Puts every changed task into the state recorded in the change.
+    fn apply(&mut self, changes: &[Change], undo: bool) {
+        for change in changes {
+            let state = if undo { &change.before } else { &change.after };
+            self.changed.insert(change.id, state.clone());
+        }
+    }
+
+    fn get_task(&self, id: u32) -> Result<Option<TaskProperties>> {
+        match self.changed.get(&id) {
+            Some(task) => Ok(task.clone()),
+            None => self.store.get(id),
+        }
+    }
+
+    /// The tasks matching `query`, with the changes not saved yet applied.
+    fn query(&self, query: &Query) -> Result<Vec<(u32, TaskProperties)>> {
+        let stored = if self.changed.is_empty() {
+            self.store.query(query)?
         } else {
-            self.tasks.insert(name.to_owned(), TaskProperties::new());
+            self.store.query(&Query {
+                limit: None,
+                ..query.clone()
+            })?
+        };
+        let mut found: BTreeMap<u32, TaskProperties> = stored
+            .into_iter()
+            .filter(|(id, _)| !self.changed.contains_key(id))
+            .collect();
+        for (id, task) in &self.changed {
+            if let Some(task) = task.as_ref().filter(|task| query.matches(task)) {
+                found.insert(*id, task.clone());
+            }
         }
+        Ok(found
+            .into_iter()
+            .take(query.limit.unwrap_or(usize::MAX))
+            .collect())
     }
 
-    fn delete_task(&mut self, name: &str) -> TaskProperties {
-        self.tasks.remove(name).unwrap()
+    /// The change history of a task, including entries not saved yet.
+    fn history(&self, uuid: Uuid) -> Result<Vec<HistoryEntry>> {
+        let mut entries = self.store.history(uuid)?;
+        entries.extend(self.history.get(&uuid).into_iter().flatten().cloned());
+        Ok(entries)
     }
 
-    fn get_mut_task(&mut self, name: &str) -> Option<&mut TaskProperties> {
-        self.tasks.get_mut(name)
+    fn find(&self, project: Option<&str>, name: &str) -> Result<Option<u32>> {
+        let query = Query {
+            name: Some(name.to_owned()),
+            project: Some(project.map(str::to_owned)),
+            archived: Some(false),
+            limit: Some(1),
+            ..Default::default()
+        };
+        Ok(self.query(&query)?.first().map(|(id, _)| *id))
     }
 
-    fn get_task(&self, name: &str) -> Option<&TaskProperties> {
-        self.tasks.get(name)
+    /// Direct subtasks of a task, in creation order.
+    fn children(&self, id: u32) -> Result<Vec<u32>> {
+        let query = Query {
+            parent: Some(id),
+            ..Default::default()
+        };
+        Ok(self.query(&query)?.into_iter().map(|(id, _)| id).collect())
     }
 
-    fn task_exists(&self, name: &str) -> bool {
-        self.tasks.contains_key(name)
+    /// All subtasks of a task, including subtasks of subtasks.
+    fn descendants(&self, id: u32) -> Result<Vec<u32>> {
+        let mut found = Vec::new();
+        let mut stack = self.children(id)?;
+        while let Some(child) = stack.pop() {
+            stack.extend(self.children(child)?);
+            found.push(child);
+        }
+        Ok(found)
+    }
+
+    /// Every task a task waits on, directly or through other dependencies.
+    fn all_dependencies(&self, id: u32) -> Result<BTreeSet<u32>> {
+        let mut found = BTreeSet::new();
+        let mut stack = vec![id];
+        while let Some(task) = stack.pop() {
+            if let Some(task) = self.get_task(task)? {
+                for dependency in task.depends {
+                    if found.insert(dependency) {
+                        stack.push(dependency);
+                    }
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    /// All tasks with this name, across every project.
+    fn find_by_name(&self, name: &str) -> Result<Vec<u32>> {
+        let query = Query {
+            name: Some(name.to_owned()),
+            archived: Some(false),
+            ..Default::default()
+        };
+        Ok(self.query(&query)?.into_iter().map(|(id, _)| id).collect())
+    }
+
+    fn find_by_uuid(&self, uuid: Uuid) -> Result<Option<u32>> {
+        let query = Query {
+            uuid: Some(uuid),
+            ..Default::default()
+        };
+        Ok(self.query(&query)?.first().map(|(id, _)| *id))
     }
 }
 
 /// Contains all properties of a task.
-#[derive(serde::Deserialize, serde::Serialize, Debug)]
-struct TaskProperties {
-    desc: String,
-    status: String,
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
+pub struct TaskProperties {
+    pub uuid: Uuid,
+    pub name: String,
+    /// Dotted project path such as `work.backend`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    /// Short ID of the task this is a subtask of.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<u32>,
+    pub desc: String,
+    pub status: Status,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub due: Option<NaiveDate>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<Priority>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+    /// Short IDs of the tasks that have to be completed before this one.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub depends: BTreeSet<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recur: Option<Recurrence>,
+    /// Completed instance of a recurring task, kept for the record. Archived
+    /// tasks can only be looked up by ID and don't take up their name.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub archived: bool,
+    #[serde(flatten)]
+    pub times: Timestamps,
+    /// Time spent on the task, one interval per stretch of being active.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub intervals: Vec<Interval>,
+    /// Timestamped notes, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<Note>,
 }
 
 impl TaskProperties {
     /// Create empty task properties.
-    fn new() -> Self {
-        Self {
+    fn new(name: &str, status: Status, now: DateTime<Utc>) -> Self {
+        let mut properties = Self {
+            uuid: Uuid::new_v4(),
+            name: name.to_owned(),
+            project: None,
+            parent: None,
             desc: String::from(""),
-            status: String::from("a"),
+            status,
+            due: None,
+            priority: None,
+            tags: BTreeSet::new(),
+            depends: BTreeSet::new(),
+            recur: None,
+            archived: false,
+            times: Timestamps::new(now),
+            intervals: Vec::new(),
+            notes: Vec::new(),
+        };
+        properties.times.record(properties.status, now);
+        properties
+    }
+
+    /// Changes the status, recording the time of the transition. Setting a
+    /// task active starts tracking time, any other status stops it.
+    fn set_status(&mut self, status: Status, now: DateTime<Utc>) {
+        if self.status != status {
+            self.status = status;
+            self.times.record(status, now);
+        }
+        let tracking = self.intervals.last().is_some_and(|i| i.end.is_none());
+        if status == Status::Active && !tracking {
+            self.intervals.push(Interval {
+                start: now,
+                end: None,
+            });
+        } else if status != Status::Active && tracking {
+            self.intervals.last_mut().unwrap().end = Some(now);
         }
     }
+
+    fn content(&self, id: u32, today: NaiveDate) -> Task {
+        Task {
+            id,
+            uuid: self.uuid,
+            name: self.name.clone(),
+            project: self.project.clone(),
+            parent: self.parent,
+            desc: self.desc.clone(),
+            status: self.status,
+            due: self.due,
+            priority: self.priority,
+            tags: self.tags.clone(),
+            depends: self.depends.iter().copied().collect(),
+            blocked: false,
+            recur: self.recur.clone(),
+            archived: self.archived,
+            overdue: self.is_open() && self.due.is_some_and(|due| due < today),
+            times: self.times.clone(),
+            intervals: self.intervals.clone(),
+            notes: self.notes.clone(),
+        }
+    }
+
+    /// Whether the task still needs to be worked on.
+    fn is_open(&self) -> bool {
+        !matches!(self.status, Status::Completed | Status::Canceled)
+    }
 }
 
-fn get_save(path: &Path) -> Option<File> {
-    // TODO Requires result
-    for e in std::fs::read_dir(path).unwrap() {
-        let entry = e.unwrap();
-        let path = entry.path();
-        if path.is_file() {
-            let name = entry.file_name();
-            if name.to_str().unwrap().eq(SAVE_FILE_NAME) {
-                return Some(std::fs::File::open(path).unwrap());
-            }
+/// A stretch of time a task was active, `end` is `None` while it still is.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
+pub struct Interval {
+    pub start: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<DateTime<Utc>>,
+}
+
+/// A note added to a task with `annotate`.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
+pub struct Note {
+    pub entry: DateTime<Utc>,
+    pub text: String,
+}
+
+/// When a task was created, last modified and last moved into a status.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
+pub struct Timestamps {
+    pub created_at: DateTime<Utc>,
+    pub modified_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canceled_at: Option<DateTime<Utc>>,
+}
+
+impl Timestamps {
+    fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            created_at: now,
+            modified_at: now,
+            started_at: None,
+            completed_at: None,
+            canceled_at: None,
         }
     }
-    None
+
+    /// Records the time a task moved into `status`.
+    fn record(&mut self, status: Status, now: DateTime<Utc>) {
+        match status {
+            Status::Active => self.started_at = Some(now),
+            Status::Completed => self.completed_at = Some(now),
+            Status::Canceled => self.canceled_at = Some(now),
+            Status::Stopped => (),
+        }
+    }
+}
+
+/// Whether `edit` completes a task that isn't completed yet, which is when
+/// a recurring task gets its next instance.
+fn completes(edit: &TaskEdit, status: Status) -> bool {
+    edit.status == Some(Status::Completed) && status != Status::Completed
+}
+
+/// Purely numeric names would be ambiguous with short IDs.
+fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || name.contains([PROJECT_SEPARATOR, LIST_SEPARATOR])
+        || name.parse::<u32>().is_ok()
+        || name.parse::<Uuid>().is_ok()
+    {
+        return Err(SystemError::InvalidName(name.to_owned()).into());
+    }
+    Ok(())
+}
+
+fn validate_project(project: &str) -> Result<()> {
+    let valid_part = |part: &str| {
+        !part.is_empty()
+            && !part.contains(|c: char| {
+                c.is_whitespace() || c == PROJECT_SEPARATOR || c == LIST_SEPARATOR
+            })
+    };
+    if !project.split('.').all(valid_part) {
+        return Err(SystemError::InvalidProject(project.to_owned()).into());
+    }
+    Ok(())
+}
+
+/// Whether `project` is `parent` or one of its subprojects.
+pub fn in_project(project: Option<&str>, parent: &str) -> bool {
+    project.is_some_and(|p| {
+        p == parent
+            || p.strip_prefix(parent)
+                .is_some_and(|rest| rest.starts_with('.'))
+    })
+}
+
+/// The name of a task prefixed with its project, if it has one.
+pub fn qualified_name(project: Option<&str>, name: &str) -> String {
+    match project {
+        Some(project) => format!("{}{}{}", project, PROJECT_SEPARATOR, name),
+        None => name.to_owned(),
+    }
 }
 
-fn create_save(save_path: &Path) -> Result<()> {
-    // TODO Requires result
-    std::fs::File::create(&save_path)?;
-    let default_data = TaskData::default();
-    let default = serde_json::to_string(&default_data)?;
-    std::fs::write(&save_path, default)?;
+fn validate_tag(tag: &str) -> Result<()> {
+    if tag.is_empty() || tag.contains(|c: char| c.is_whitespace() || c == ',') {
+        return Err(SystemError::InvalidTag(tag.to_owned()).into());
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use chrono::{TimeDelta, TimeZone};
+
+    use super::*;
+    use crate::{report::TimeReport, store::MemoryStore};
+
+    /// A handler on an empty in-memory store, its clock only moves when the
+    /// returned cell is set.
+    fn handler() -> (TaskHandler, Rc<Cell<DateTime<Utc>>>) {
+        let now = Rc::new(Cell::new(
+            Utc.with_ymd_and_hms(2024, 3, 1, 9, 0, 0).unwrap(),
+        ));
+        let clock = {
+            let now = Rc::clone(&now);
+            move || now.get()
+        };
+        let handler = TaskHandler::new(Box::new(MemoryStore::default()), Box::new(clock)).unwrap();
+        (handler, now)
+    }
+
+    fn set_status(handler: &mut TaskHandler, id: u32, status: Status) {
+        let edit = TaskEdit {
+            status: Some(status),
+            ..Default::default()
+        };
+        handler.edit_task(id, edit).unwrap();
+    }
+
+    #[test]
+    fn records_times_from_the_clock() {
+        let (mut handler, now) = handler();
+        let created = now.get();
+        let id = handler.create_task("write", None).unwrap();
+        let task = handler.get_content(id).unwrap();
+        assert_eq!(task.times.created_at, created);
+        assert_eq!(task.times.modified_at, created);
+        assert_eq!(task.times.started_at, Some(created));
+        assert!(task.intervals.is_empty());
+
+        let started = created + TimeDelta::minutes(30);
+        now.set(started);
+        set_status(&mut handler, id, Status::Active);
+        let stopped = created + TimeDelta::hours(1);
+        now.set(stopped);
+        set_status(&mut handler, id, Status::Stopped);
+        let completed = created + TimeDelta::hours(3);
+        now.set(completed);
+        set_status(&mut handler, id, Status::Completed);
+
+        let task = handler.get_content(id).unwrap();
+        assert_eq!(task.times.created_at, created);
+        assert_eq!(task.times.modified_at, completed);
+        assert_eq!(task.times.started_at, Some(created));
+        assert_eq!(task.times.completed_at, Some(completed));
+        assert_eq!(task.times.canceled_at, None);
+        assert_eq!(
+            task.intervals,
+            vec![Interval {
+                start: started,
+                end: Some(stopped),
+            }]
+        );
+    }
+
+    #[test]
+    fn upgrades_saves_with_the_clock() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(store::SAVE_FILE_NAME);
+        std::fs::write(
+            &path,
+            r#"{"version": 1, "tasks": {"plan": {"desc": "", "status": "stopped"}}}"#,
+        )
+        .unwrap();
+        let now = Utc.with_ymd_and_hms(2024, 3, 1, 9, 0, 0).unwrap();
+        let handler = TaskHandler::open(&path, Box::new(move || now), Duration::ZERO).unwrap();
+
+        let task = handler.get_content(1).unwrap();
+        assert_eq!(task.name, "plan");
+        assert_eq!(task.times.created_at, now);
+        assert_eq!(task.times.modified_at, now);
+    }
+
+    #[test]
+    fn canceling_stops_tracking() {
+        let (mut handler, now) = handler();
+        let id = handler.create_task("write", None).unwrap();
+        set_status(&mut handler, id, Status::Active);
+        let canceled = now.get() + TimeDelta::minutes(20);
+        now.set(canceled);
+        set_status(&mut handler, id, Status::Canceled);
+
+        let task = handler.get_content(id).unwrap();
+        assert_eq!(task.times.canceled_at, Some(canceled));
+        assert_eq!(task.intervals[0].end, Some(canceled));
+    }
+
+    #[test]
+    fn new_tasks_record_no_time() {
+        let (mut handler, now) = handler();
+        let id = handler.create_task("write", None).unwrap();
+        now.set(now.get() + TimeDelta::days(7));
+
+        let task = handler.get_content(id).unwrap();
+        assert_eq!(task.status, Status::Active);
+        assert!(task.intervals.is_empty());
+        let report = TimeReport::new(
+            &handler.all_content().unwrap(),
+            None,
+            now.get().date_naive(),
+            now.get(),
+        )
+        .unwrap();
+        assert_eq!(report.total, 0);
+        assert!(report.days.is_empty());
+    }
+
+    #[test]
+    fn reports_leave_out_empty_days() {
+        let (mut handler, now) = handler();
+        let id = handler.create_task("write", None).unwrap();
+        set_status(&mut handler, id, Status::Active);
+        now.set(now.get() + TimeDelta::milliseconds(400));
+        set_status(&mut handler, id, Status::Stopped);
+
+        let today = now.get().date_naive();
+        let report =
+            TimeReport::new(&handler.all_content().unwrap(), None, today, now.get()).unwrap();
+        assert!(report.tasks.is_empty());
+        assert!(report.days.is_empty());
+    }
+
+    #[test]
+    fn reports_reject_reversed_ranges() {
+        let (handler, now) = handler();
+        let today = now.get().date_naive();
+        let tomorrow = today.succ_opt().unwrap();
+        let report = TimeReport::new(
+            &handler.all_content().unwrap(),
+            Some(tomorrow),
+            today,
+            now.get(),
+        );
+        assert!(report.is_err_and(|err| matches!(
+            err.kind(),
+            ErrorType::System(SystemError::ReversedRange(..))
+        )));
+    }
+
+    #[test]
+    fn stopped_tasks_start_untracked() {
+        let (mut handler, _) = handler();
+        handler.set_default_status(Status::Stopped);
+        let id = handler.create_task("write", None).unwrap();
+
+        let task = handler.get_content(id).unwrap();
+        assert_eq!(task.times.started_at, None);
+        assert!(task.intervals.is_empty());
+    }
+
+    #[test]
+    fn adding_a_recurrence_to_a_completed_task_keeps_it() {
+        let (mut handler, _) = handler();
+        let id = handler.create_task("water", None).unwrap();
+        set_status(&mut handler, id, Status::Completed);
+        let edit = TaskEdit {
+            recur: Some(Some(Recurrence::Daily)),
+            ..Default::default()
+        };
+        let msg = handler.edit_task(id, edit).unwrap();
+
+        assert!(matches!(msg, Message::AppliedTaskChanges(_)));
+        assert_eq!(handler.all_content().unwrap().len(), 1);
+        let task = handler.get_content(id).unwrap();
+        assert!(!task.archived);
+        assert_eq!(task.recur, Some(Recurrence::Daily));
+    }
+
+    #[test]
+    fn next_instances_start_untracked() {
+        let (mut handler, now) = handler();
+        let id = handler.create_task("water", None).unwrap();
+        let edit = TaskEdit {
+            recur: Some(Some(Recurrence::Daily)),
+            ..Default::default()
+        };
+        handler.edit_task(id, edit).unwrap();
+        set_status(&mut handler, id, Status::Active);
+        now.set(now.get() + TimeDelta::hours(1));
+        let edit = TaskEdit {
+            status: Some(Status::Completed),
+            ..Default::default()
+        };
+        let Message::Recurred(_, next, _) = handler.edit_task(id, edit).unwrap() else {
+            panic!("completing a recurring task didn't recur");
+        };
+
+        let next = handler.get_content(next).unwrap();
+        assert_eq!(next.status, Status::Active);
+        assert!(next.intervals.is_empty());
+        assert_eq!(handler.get_content(id).unwrap().intervals.len(), 1);
+    }
+
+    #[test]
+    fn names_cant_hold_a_list_of_tasks() {
+        let (mut handler, _) = handler();
+        let invalid = |result: Result<_>| {
+            result.is_err_and(|err| {
+                matches!(
+                    err.kind(),
+                    ErrorType::System(SystemError::InvalidName(_) | SystemError::InvalidProject(_))
+                )
+            })
+        };
+        assert!(invalid(handler.create_task("a,b", None).map(|_| ())));
+        assert!(invalid(handler.create_task("a", Some("x,y")).map(|_| ())));
+
+        let id = handler.create_task("a", None).unwrap();
+        let edit = TaskEdit {
+            name: Some("a,b"),
+            ..Default::default()
+        };
+        assert!(invalid(handler.edit_task(id, edit).map(|_| ())));
+        assert_eq!(handler.get_content(id).unwrap().name, "a");
+    }
+
+    #[test]
+    fn restoring_keeps_the_change_history() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [store::SAVE_FILE_NAME, store::DB_FILE_NAME] {
+            restore_in(&dir.path().join(name));
+        }
+    }
+
+    /// Saves two runs to the store at `path` and restores the first.
+    fn restore_in(path: &Path) {
+        let now = Utc.with_ymd_and_hms(2024, 3, 1, 9, 0, 0).unwrap();
+        let open = || TaskHandler::open(path, Box::new(move || now), Duration::ZERO).unwrap();
+        let run = |change: &dyn Fn(&mut TaskHandler)| {
+            let mut handler = open();
+            change(&mut handler);
+            handler.commit("task").unwrap();
+            handler.save().unwrap();
+        };
+        run(&|handler| {
+            handler.create_task("write", None).unwrap();
+        });
+        run(&|handler| {
+            let edit = TaskEdit {
+                desc: Some("report"),
+                ..Default::default()
+            };
+            handler.edit_task(1, edit).unwrap();
+            handler.create_task("read", None).unwrap();
+        });
+        let write = open().get_content(1).unwrap().uuid;
+        let read = open().get_content(2).unwrap().uuid;
+        let fields = |handler: &TaskHandler, uuid: Uuid| -> Vec<String> {
+            let history = handler.history(&uuid.to_string()).unwrap();
+            history.into_iter().map(|entry| entry.field).collect()
+        };
+
+        run(&|handler| {
+            handler.restore(1).unwrap();
+        });
+        let mut handler = open();
+        assert_eq!(handler.get_content(1).unwrap().desc, "");
+        assert!(handler.get_content(2).is_err());
+        assert_eq!(fields(&handler, write), ["created", "desc", "restored"]);
+        assert_eq!(fields(&handler, read), ["created", "restored"]);
+        // The restored undo history reaches back to before the backup.
+        assert!(handler.undo().is_ok());
+        assert!(nothing_to(handler.undo()));
+        // IDs given out since the backup stay taken.
+        assert_eq!(handler.create_task("read", None).unwrap(), 3);
+    }
+
+    /// Whether undo or redo failed for lack of anything to undo or redo.
+    fn nothing_to(result: Result<Message>) -> bool {
+        result.is_err_and(|err| {
+            matches!(
+                err.kind(),
+                ErrorType::System(SystemError::NothingToUndo | SystemError::NothingToRedo)
+            )
+        })
+    }
+
+    #[test]
+    fn undo_and_redo_round_trip() {
+        let (mut handler, _) = handler();
+        let id = handler.create_task("write", None).unwrap();
+        handler.commit("new write").unwrap();
+        let edit = TaskEdit {
+            desc: Some("report"),
+            status: Some(Status::Completed),
+            ..Default::default()
+        };
+        handler.edit_task(id, edit).unwrap();
+        handler.commit("edit write").unwrap();
+        handler.save().unwrap();
+        let done = handler.get_content(id).unwrap();
+
+        let undone = handler.undo().unwrap();
+        assert!(matches!(undone, Message::Undone(command, 1) if command == "edit write"));
+        let task = handler.get_content(id).unwrap();
+        assert_eq!(task.status, Status::Active);
+        assert_eq!(task.desc, "");
+        handler.save().unwrap();
+
+        handler.undo().unwrap();
+        assert!(!handler.task_exists(None, "write").unwrap());
+        assert!(nothing_to(handler.undo()));
+        handler.save().unwrap();
+
+        handler.redo().unwrap();
+        handler.redo().unwrap();
+        let task = handler.get_content(id).unwrap();
+        assert_eq!(task.uuid, done.uuid);
+        assert_eq!(task.status, Status::Completed);
+        assert_eq!(task.desc, "report");
+        assert_eq!(task.times, done.times);
+        assert!(nothing_to(handler.redo()));
+    }
+
+    #[test]
+    fn new_changes_drop_the_redo_history() {
+        let (mut handler, _) = handler();
+        handler.create_task("write", None).unwrap();
+        handler.commit("new write").unwrap();
+        handler.undo().unwrap();
+        handler.create_task("read", None).unwrap();
+        handler.commit("new read").unwrap();
+        assert!(nothing_to(handler.redo()));
+        assert!(handler.task_exists(None, "read").unwrap());
+        assert!(!handler.task_exists(None, "write").unwrap());
+    }
+
+    #[test]
+    fn undoing_a_delete_restores_links() {
+        let (mut handler, _) = handler();
+        let parent = handler.create_task("plan", None).unwrap();
+        let id = handler.create_task("write", None).unwrap();
+        let child = handler.create_task("outline", None).unwrap();
+        let dependent = handler.create_task("publish", None).unwrap();
+        for (task, edit) in [
+            (
+                id,
+                TaskEdit {
+                    parent: Some(Some(parent)),
+                    ..Default::default()
+                },
+            ),
+            (
+                child,
+                TaskEdit {
+                    parent: Some(Some(id)),
+                    ..Default::default()
+                },
+            ),
+            (
+                dependent,
+                TaskEdit {
+                    add_depends: vec![id],
+                    ..Default::default()
+                },
+            ),
+        ] {
+            handler.edit_task(task, edit).unwrap();
+        }
+        handler.commit("setup").unwrap();
+        handler.save().unwrap();
+
+        handler.delete_task(id).unwrap();
+        handler.commit("delete write").unwrap();
+        handler.save().unwrap();
+        assert_eq!(handler.get_content(child).unwrap().parent, Some(parent));
+        assert!(handler.get_content(dependent).unwrap().depends.is_empty());
+
+        handler.undo().unwrap();
+        handler.save().unwrap();
+        assert_eq!(handler.get_content(id).unwrap().parent, Some(parent));
+        assert_eq!(handler.get_content(child).unwrap().parent, Some(id));
+        assert_eq!(handler.get_content(dependent).unwrap().depends, [id]);
+    }
+}
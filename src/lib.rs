@@ -0,0 +1,20 @@
+//! Tasks with projects, dependencies, recurrence and time tracking, kept in
+//! a JSON or SQLite store. The `task` binary is one user of this crate, other
+//! tools can open the same store through [`TaskHandler`].
+
+pub mod clock;
+pub mod date;
+pub mod filter;
+pub mod handler;
+pub mod locate;
+pub mod recur;
+pub mod report;
+pub mod result;
+pub mod store;
+pub mod task;
+
+pub use filter::Filter;
+pub use handler::{TaskEdit, TaskHandler};
+pub use result::{Error, ErrorType, Result};
+pub use store::{Query, TaskStore};
+pub use task::{Priority, SortOrder, Status, Task};
@@ -0,0 +1,135 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    result::{Result, SystemError},
+    store::{self, DB_FILE_NAME, SAVE_FILE_NAME},
+};
+
+/// Environment variable pointing at the store, overridden by an explicit file.
+const FILE_VAR: &str = "TASK_FILE";
+
+/// Directory holding a project's own store, created by `task init`.
+const PROJECT_DIR: &str = ".task";
+
+/// Keeps locks, temporary files and backups out of version control.
+const PROJECT_GITIGNORE: &str = "*.lock\n*.tmp\ntask.json.*\ntask.db.*\ntask.db-journal\n";
+
+/// The store the CLI uses given its `--file`, `--global` and `store`
+/// setting: `file`, then `$TASK_FILE`, then a project store in the current
+/// directory or one of its ancestors (unless `global`), then `configured`,
+/// then the user's data dir (see [`store::default_path`]).
+pub fn store_path(file: Option<&Path>, global: bool, configured: Option<&Path>) -> Result<PathBuf> {
+    let env_file = std::env::var_os(FILE_VAR)
+        .filter(|f| !f.is_empty())
+        .map(PathBuf::from);
+    let project = || {
+        if global {
+            return Ok(None);
+        }
+        Ok(find_project_store(&std::env::current_dir()?))
+    };
+    choose(file.or(env_file.as_deref()), project, configured)
+}
+
+/// The first of `file`, the `project` store and the `configured` one, or
+/// the default store when there is none.
+fn choose(
+    file: Option<&Path>,
+    project: impl FnOnce() -> Result<Option<PathBuf>>,
+    configured: Option<&Path>,
+) -> Result<PathBuf> {
+    if let Some(file) = file {
+        return Ok(file.to_path_buf());
+    }
+    if let Some(path) = project()? {
+        return Ok(path);
+    }
+    if let Some(store) = configured {
+        return Ok(store.to_path_buf());
+    }
+    store::default_path()
+}
+
+/// Looks for a `.task/` store or a bare `task.db` or `task.json` in `dir`
+/// and its ancestors, the closest one wins.
+fn find_project_store(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors().find_map(|dir| {
+        let project_dir = dir.join(PROJECT_DIR);
+        if project_dir.is_dir() {
+            return Some(store::in_dir(&project_dir));
+        }
+        [DB_FILE_NAME, SAVE_FILE_NAME]
+            .into_iter()
+            .map(|name| dir.join(name))
+            .find(|file| file.is_file())
+    })
+}
+
+/// Creates a `.task/` store in `dir` for `task init`, returning the path
+/// of its `task.json`.
+pub fn init_project_store(dir: &Path) -> Result<PathBuf> {
+    let project_dir = dir.join(PROJECT_DIR);
+    if project_dir.exists() {
+        return Err(SystemError::StoreExists(project_dir).into());
+    }
+    std::fs::create_dir_all(&project_dir)?;
+    std::fs::write(project_dir.join(".gitignore"), PROJECT_GITIGNORE)?;
+    Ok(project_dir.join(SAVE_FILE_NAME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chooses_the_first_store_given() {
+        let file = Path::new("file.json");
+        let project = || Ok(Some(PathBuf::from(".task/task.json")));
+        let configured = Some(Path::new("configured.db"));
+        assert_eq!(choose(Some(file), project, configured).unwrap(), file);
+        assert_eq!(
+            choose(None, project, configured).unwrap(),
+            Path::new(".task/task.json")
+        );
+        assert_eq!(
+            choose(None, || Ok(None), configured).unwrap(),
+            Path::new("configured.db")
+        );
+        assert_eq!(
+            choose(None, || Ok(None), None).unwrap(),
+            store::default_path().unwrap()
+        );
+    }
+
+    #[test]
+    fn the_closest_project_store_wins() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let nested = root.join("src/store");
+        std::fs::create_dir_all(&nested).unwrap();
+        let outside = |found: Option<PathBuf>| found.is_none_or(|path| !path.starts_with(root));
+        assert!(outside(find_project_store(&nested)));
+
+        let project_store = init_project_store(root).unwrap();
+        assert_eq!(project_store, root.join(".task").join(SAVE_FILE_NAME));
+        assert_eq!(find_project_store(&nested), Some(project_store.clone()));
+        // A bare store next to `.task/` loses, a closer one wins.
+        std::fs::write(root.join(SAVE_FILE_NAME), "").unwrap();
+        assert_eq!(find_project_store(root), Some(project_store));
+        let closer = root.join("src").join(DB_FILE_NAME);
+        std::fs::write(&closer, "").unwrap();
+        assert_eq!(find_project_store(&nested), Some(closer));
+    }
+
+    #[test]
+    fn init_keeps_an_existing_project_store() {
+        let dir = tempfile::tempdir().unwrap();
+        init_project_store(dir.path()).unwrap();
+        let gitignore = std::fs::read_to_string(dir.path().join(".task/.gitignore")).unwrap();
+        assert_eq!(gitignore, PROJECT_GITIGNORE);
+        assert!(init_project_store(dir.path()).is_err_and(|err| matches!(
+            err.kind(),
+            crate::ErrorType::System(SystemError::StoreExists(_))
+        )));
+    }
+}
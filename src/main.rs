@@ -1,26 +1,111 @@
+use std::time::Duration;
+
 use app::get_app;
 
+use task::{
+    clock::{self, Clock},
+    date, handler, locate, result, store,
+};
+
 mod app;
 mod commands;
-mod handler;
+mod config;
 mod output;
-mod result;
+mod paths;
 
 fn main() {
-    let matches = get_app().get_matches();
-    let mut output = output::Output::init();
+    let (config, config_error) = match config::Config::load() {
+        Ok(config) => (config, None),
+        Err(e) => (config::Config::default(), Some(e)),
+    };
+    date::set_formats(&config.date_format, &config.time_format);
+    let mut output = output::Output::init(&config);
+    if let Some(e) = config_error {
+        output.write(e);
+    }
+
+    let args = config.expand_aliases(std::env::args().collect());
+    let matches = get_app().get_matches_from(&args);
+    let dry_run = matches.is_present("dry-run");
+    if dry_run {
+        // These write their files directly, not through the store.
+        let writes = match matches.subcommand() {
+            Some((command @ ("init" | "migrate-store"), _)) => Some(command.to_owned()),
+            Some(("config", args)) if args.subcommand_name() == Some("set") => {
+                Some(String::from("config set"))
+            }
+            _ => None,
+        };
+        if let Some(command) = writes {
+            output.fatal_error(result::Error::from(result::SystemError::NoDryRun(command)));
+        }
+    }
+
+    if let Some(("config", args)) = matches.subcommand() {
+        match commands::config_cmd(args, &config, &mut output) {
+            Ok(Some(msg)) => output.write(msg),
+            Ok(None) => (),
+            Err(e) => output.write(e),
+        }
+        return;
+    }
 
-    let mut current_path = std::env::current_exe().unwrap();
-    current_path.pop();
-    let mut handler = match handler::TaskHandler::from_json(&current_path) {
+    let found = match matches.subcommand_name() {
+        Some("init") => std::env::current_dir()
+            .map_err(result::Error::from)
+            .and_then(|dir| locate::init_project_store(&dir))
+            .map(|path| {
+                let msg = result::Message::InitializedStore(path.clone());
+                (path, Some(msg))
+            }),
+        _ => paths::store_path(&matches, &config),
+    };
+    let (store_path, notice) = match found {
+        Ok(found) => found,
+        Err(e) => output.fatal_error(e),
+    };
+    if let Some(msg) = notice {
+        output.write(msg);
+    }
+    let lock_timeout = matches
+        .value_of_t("lock-timeout")
+        .unwrap_or(config.lock_timeout);
+    let lock_timeout = Duration::from_secs_f64(lock_timeout);
+    if let Some(("migrate-store", args)) = matches.subcommand() {
+        let to = store::Backend::named(args.value_of("to").unwrap()).unwrap();
+        match store::migrate(&store_path, to, lock_timeout, clock::SystemClock.now()) {
+            Ok(msg) => output.write(msg),
+            Err(e) => output.fatal_error(e),
+        }
+        return;
+    }
+    let handler = if dry_run {
+        store::read(&store_path, clock::SystemClock.now()).and_then(|snapshot| {
+            let store = Box::new(store::MemoryStore::from(snapshot));
+            handler::TaskHandler::new(store, Box::new(clock::SystemClock))
+        })
+    } else {
+        handler::TaskHandler::open(&store_path, Box::new(clock::SystemClock), lock_timeout)
+    };
+    let mut handler = match handler {
         Ok(h) => h,
         Err(e) => output.fatal_error(e),
     };
 
-    commands::process_matches(&matches, &mut handler, &mut output);
+    if let Some(user) = config.user.clone().or_else(|| std::env::var("USER").ok()) {
+        handler.set_user(&user);
+    }
+    handler.set_default_status(config.default_status);
+    commands::process_matches(&matches, &mut handler, &mut output, &config);
 
-    match handler.save() {
+    match handler
+        .commit(&format!("task {}", args[1..].join(" ")))
+        .and_then(|_| handler.save())
+    {
         Ok(_) => (),
         Err(e) => output.fatal_error(e),
     };
+    if dry_run {
+        output.write(result::Message::DryRun);
+    }
 }
@@ -1,97 +1,173 @@
 use std::{
-    fmt::Display,
+    collections::{BTreeMap, BTreeSet},
     io::{self, Write},
 };
 
+use chrono::{DateTime, Utc};
 use colored::Colorize;
 use comfy_table::{presets, Attribute, Cell, CellAlignment, Color, ContentArrangement, Table};
 
-const TABLE_LEGEND: &str = "\n✅ - completed, 🟢 - active, 🟡 - paused, 🔴 - canceled";
+use task::{
+    date::{format_date, format_time},
+    handler::HistoryEntry,
+    report::{format_duration, TimeReport},
+    store::Backup,
+    Priority, SortOrder, Status, Task,
+};
+
+use crate::config::Config;
+
+const TABLE_LEGEND: &str =
+    "\n✅ - completed, 🟢 - active, 🟡 - paused, 🔴 - canceled, ⛔ - blocked";
+
+/// Builds the task table, each row is a task and its depth in the tree view.
+fn task_table(mut table: Table, rows: &[(usize, &Task)], with_project: bool) -> Table {
+    let mut header = vec!["ID", "Name"];
+    if with_project {
+        header.push("Project");
+    }
+    header.extend(["Status", "Pri", "Due", "Tags", "Description"]);
+
+    table.set_header(
+        header
+            .into_iter()
+            .map(|h| Cell::new(h).add_attribute(Attribute::Bold)),
+    );
 
-#[derive(Debug)]
-pub struct TaskContent {
-    pub name: String,
-    pub desc: String,
-    pub status: Status,
+    for &(depth, task) in rows {
+        let name = match depth {
+            0 => task.name.clone(),
+            _ => format!("{}└ {}", "  ".repeat(depth - 1), task.name),
+        };
+        let mut row = vec![
+            Cell::new(task.id).set_alignment(CellAlignment::Right),
+            Cell::new(name).fg(Color::Yellow),
+        ];
+        if with_project {
+            row.push(Cell::new(task.project.as_deref().unwrap_or("")));
+        }
+        row.extend([
+            status_cell(task).set_alignment(CellAlignment::Center),
+            priority_cell(task).set_alignment(CellAlignment::Center),
+            due_cell(task),
+            tags_cell(task),
+            Cell::new(&task.desc),
+        ]);
+        table.add_row(row);
+    }
+    table
 }
 
-impl TaskContent {
-    pub fn new(name: &str, desc: &str, status: Status) -> Self {
-        Self {
-            name: name.to_owned(),
-            desc: desc.to_owned(),
-            status,
+/// Orders the tasks depth first, each subtask under its parent. Tasks whose
+/// parent isn't shown are treated as top level tasks.
+fn tree_rows(content: &[Task]) -> Vec<(usize, &Task)> {
+    let shown: BTreeSet<u32> = content.iter().map(|t| t.id).collect();
+    let mut children: BTreeMap<u32, Vec<&Task>> = BTreeMap::new();
+    let mut roots = Vec::new();
+    for task in content {
+        match task.parent {
+            Some(parent) if shown.contains(&parent) => {
+                children.entry(parent).or_default().push(task)
+            }
+            _ => roots.push(task),
         }
     }
 
-    pub fn sort_by(vec: &mut Vec<Self>, order: SortOrder) {
-        match order {
-            SortOrder::Alphabetical => vec.sort_by(|a, b| a.name.cmp(&b.name)),
-            SortOrder::ReverseAlphabetical => vec.sort_by(|a, b| b.name.cmp(&a.name)),
-            SortOrder::Status => vec.sort_by(|a, b| a.status.cmp(&b.status)),
-            SortOrder::ReverseStatus => vec.sort_by(|a, b| b.status.cmp(&a.status)),
+    let mut rows = Vec::new();
+    let mut stack: Vec<(usize, &Task)> = roots.into_iter().rev().map(|t| (0, t)).collect();
+    while let Some((depth, task)) = stack.pop() {
+        rows.push((depth, task));
+        if let Some(subtasks) = children.get(&task.id) {
+            stack.extend(subtasks.iter().rev().map(|t| (depth + 1, *t)));
         }
     }
+    rows
 }
 
-#[derive(Debug, Eq, Clone, Copy)]
-pub enum Status {
-    Active,
-    Stopped,
-    Canceled,
-    Completed,
+fn status_cell(task: &Task) -> Cell {
+    if task.blocked {
+        Cell::new(format!("{}⛔", task.status))
+    } else {
+        Cell::new(task.status)
+    }
 }
 
-impl Ord for Status {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        (*self as i32).cmp(&(*other as i32))
-    }
+fn depends_cell(task: &Task) -> Cell {
+    let depends: Vec<String> = task.depends.iter().map(u32::to_string).collect();
+    Cell::new(depends.join(", "))
 }
 
-impl PartialOrd for Status {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
+fn priority_cell(task: &Task) -> Cell {
+    match task.priority {
+        Some(p @ Priority::High) => Cell::new(p).fg(Color::Red),
+        Some(p @ Priority::Medium) => Cell::new(p).fg(Color::Yellow),
+        Some(p @ Priority::Low) => Cell::new(p).fg(Color::Green),
+        None => Cell::new(""),
     }
 }
 
-impl PartialEq for Status {
-    fn eq(&self, other: &Self) -> bool {
-        core::mem::discriminant(self) == core::mem::discriminant(other)
-    }
+fn tags_cell(task: &Task) -> Cell {
+    let tags: Vec<String> = task.tags.iter().map(|t| format!("+{}", t)).collect();
+    Cell::new(tags.join(" ")).fg(Color::Cyan)
 }
 
-impl Display for Status {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Status::Completed => write!(f, "✅"),
-            Status::Active => write!(f, "🟢"),
-            Status::Stopped => write!(f, "🟡"),
-            Status::Canceled => write!(f, "🔴"),
-        }
+/// Overdue tasks have their due date highlighted in red, recurring tasks
+/// are marked with ↻.
+fn due_cell(task: &Task) -> Cell {
+    let mut text = task.due.map_or(String::new(), format_date);
+    if task.recur.is_some() {
+        text.push_str(" ↻");
+    }
+    let cell = Cell::new(text.trim_start());
+    if task.overdue {
+        cell.fg(Color::Red).add_attribute(Attribute::Bold)
+    } else {
+        cell
     }
 }
 
-impl From<&str> for Status {
-    fn from(s: &str) -> Self {
-        match s {
-            "f" => Self::Completed,
-            "a" => Self::Active,
-            "s" => Self::Stopped,
-            "c" => Self::Canceled,
-            &_ => unreachable!("Unreachable"),
-        }
+/// Strings are shown without quotes and unset values as nothing.
+fn history_value(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(s) => s.clone(),
+        value => value.to_string(),
     }
 }
 
 pub struct Output {
     stdout: io::Stdout,
+    table_width: u16,
+    info_width: u16,
+    color: bool,
 }
 
 impl Output {
-    pub fn init() -> Self {
+    pub fn init(config: &Config) -> Self {
         let stdout = io::stdout();
+        if !config.color {
+            colored::control::set_override(false);
+        }
 
-        Self { stdout }
+        Self {
+            stdout,
+            table_width: config.table_width,
+            info_width: config.info_width,
+            color: config.color,
+        }
+    }
+
+    /// An empty table in the common style.
+    fn table(&self, width: u16) -> Table {
+        let mut table = Table::new();
+        table
+            .set_table_width(width)
+            .load_preset(presets::UTF8_FULL)
+            .set_content_arrangement(ContentArrangement::Dynamic);
+        if !self.color {
+            table.force_no_tty();
+        }
+        table
     }
 
     pub fn write<T: std::fmt::Display>(&mut self, msg: T) {
@@ -102,6 +178,17 @@ impl Output {
         })
     }
 
+    /// Asks a yes/no question on stdin, anything but yes counts as no.
+    pub fn confirm(&mut self, question: &str) -> bool {
+        self.write(format!("{} [y/N] ", question));
+        self.stdout.flush().unwrap_or_default();
+        let mut answer = String::new();
+        if io::stdin().read_line(&mut answer).is_err() {
+            return false;
+        }
+        matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+    }
+
     pub fn write_all<T: std::fmt::Display>(&mut self, msgs: Vec<T>) {
         for msg in msgs {
             self.write(msg);
@@ -115,53 +202,232 @@ impl Output {
         std::process::abort();
     }
 
-    pub fn print_table(&mut self, mut content: Vec<TaskContent>, order: SortOrder) {
-        TaskContent::sort_by(&mut content, order);
-        let mut table = Table::new();
+    pub fn print_table(
+        &mut self,
+        mut content: Vec<Task>,
+        orders: &[SortOrder],
+        layout: TableLayout,
+    ) {
+        Task::sort_by(&mut content, orders);
+        match layout {
+            TableLayout::Flat => {
+                let rows: Vec<_> = content.iter().map(|task| (0, task)).collect();
+                self.write(task_table(self.table(self.table_width), &rows, true));
+            }
+            TableLayout::Grouped => {
+                let mut projects: BTreeMap<Option<&str>, Vec<_>> = BTreeMap::new();
+                for task in &content {
+                    projects
+                        .entry(task.project.as_deref())
+                        .or_default()
+                        .push((0, task));
+                }
+                for (i, (project, rows)) in projects.into_iter().enumerate() {
+                    if i > 0 {
+                        self.write("\n");
+                    }
+                    self.write(format!("{}\n", project.unwrap_or("(no project)").bold()));
+                    self.write(task_table(self.table(self.table_width), &rows, false));
+                }
+            }
+            TableLayout::Tree => {
+                let rows = tree_rows(&content);
+                self.write(task_table(self.table(self.table_width), &rows, true));
+            }
+        }
+        self.write(TABLE_LEGEND);
+    }
+
+    /// Shows every property of a task, `now` ends the interval it's
+    /// currently being tracked in, if any.
+    pub fn print_task(&mut self, task: Task, subtasks: Vec<Task>, now: DateTime<Utc>) {
+        let mut table = self.table(self.info_width);
         table
-            .set_table_width(67)
-            .load_preset(presets::UTF8_FULL)
-            .set_content_arrangement(ContentArrangement::Dynamic)
-            .set_header(vec![
-                Cell::new("Name").add_attribute(Attribute::Bold),
-                Cell::new("Status").add_attribute(Attribute::Bold),
-                Cell::new("Description").add_attribute(Attribute::Bold),
-            ]);
+            .add_row(vec![Cell::new("ID"), Cell::new(task.id)])
+            .add_row(vec![
+                Cell::new("Name"),
+                Cell::new(&task.name).fg(Color::Yellow),
+            ])
+            .add_row(vec![
+                Cell::new("Project"),
+                Cell::new(task.project.as_deref().unwrap_or("")),
+            ])
+            .add_row(vec![
+                Cell::new("Parent"),
+                Cell::new(task.parent.map_or(String::new(), |p| p.to_string())),
+            ])
+            .add_row(vec![Cell::new("Status"), status_cell(&task)])
+            .add_row(vec![Cell::new("Priority"), priority_cell(&task)])
+            .add_row(vec![Cell::new("Due"), due_cell(&task)])
+            .add_row(vec![
+                Cell::new("Recurs"),
+                Cell::new(task.recur.as_ref().map_or(String::new(), |r| r.to_string())),
+            ])
+            .add_row(vec![Cell::new("Tags"), tags_cell(&task)])
+            .add_row(vec![Cell::new("Depends on"), depends_cell(&task)])
+            .add_row(vec![Cell::new("Description"), Cell::new(&task.desc)]);
+        if !task.notes.is_empty() {
+            let notes: Vec<String> = task
+                .notes
+                .iter()
+                .enumerate()
+                .map(|(i, note)| format!("{}. {} {}", i + 1, format_time(note.entry), note.text))
+                .collect();
+            table.add_row(vec![Cell::new("Notes"), Cell::new(notes.join("\n"))]);
+        }
 
-        for task in &content {
+        let times = [
+            ("Created", Some(task.times.created_at)),
+            ("Modified", Some(task.times.modified_at)),
+            ("Started", task.times.started_at),
+            ("Completed", task.times.completed_at),
+            ("Canceled", task.times.canceled_at),
+        ];
+        for (label, time) in times {
+            if let Some(time) = time {
+                table.add_row(vec![Cell::new(label), Cell::new(format_time(time))]);
+            }
+        }
+        if !task.intervals.is_empty() {
+            let tracked: i64 = task
+                .intervals
+                .iter()
+                .map(|i| (i.end.unwrap_or(now) - i.start).num_seconds())
+                .sum();
             table.add_row(vec![
-                Cell::new(&task.name).fg(Color::Yellow),
-                Cell::new(task.status).set_alignment(CellAlignment::Center),
-                Cell::new(&task.desc),
+                Cell::new("Tracked"),
+                Cell::new(format_duration(tracked)),
+            ]);
+        }
+        if !subtasks.is_empty() {
+            // Canceled subtasks don't count towards the progress.
+            let total = subtasks
+                .iter()
+                .filter(|t| t.status != Status::Canceled)
+                .count();
+            let done = subtasks
+                .iter()
+                .filter(|t| t.status == Status::Completed)
+                .count();
+            let list: Vec<String> = subtasks
+                .iter()
+                .map(|t| format!("{} {} {}", t.status, t.id, t.name))
+                .collect();
+            table.add_row(vec![
+                Cell::new(format!("Subtasks {}/{}", done, total)),
+                Cell::new(list.join("\n")),
             ]);
         }
+        table.add_row(vec![Cell::new("UUID"), Cell::new(task.uuid)]);
 
         self.write(table);
         self.write(TABLE_LEGEND);
     }
 
-    pub fn print_task(&mut self, task: TaskContent) {
-        let mut table = Table::new();
-        table
-            .set_table_width(40)
-            .load_preset(presets::UTF8_FULL)
-            .set_content_arrangement(ContentArrangement::Dynamic)
-            .set_header(vec!["Name", "Status", "Description"])
-            .add_row(vec![
-                Cell::new(task.name),
-                Cell::new(task.status).set_alignment(CellAlignment::Center),
-                Cell::new(task.desc),
+    pub fn print_backups(&mut self, backups: &[Backup]) {
+        let mut table = self.table(self.info_width);
+        table.set_header(
+            ["Backup", "Saved", "Tasks"].map(|h| Cell::new(h).add_attribute(Attribute::Bold)),
+        );
+        for backup in backups {
+            table.add_row(vec![
+                Cell::new(backup.number),
+                Cell::new(format_time(backup.saved_at)),
+                Cell::new(
+                    backup
+                        .tasks
+                        .map_or(String::from("unreadable"), |t| t.to_string()),
+                ),
             ]);
+        }
+        self.write(format!("{}\n", table));
+    }
 
-        self.write(table);
-        self.write(TABLE_LEGEND);
+    pub fn print_history(&mut self, history: &[HistoryEntry]) {
+        let mut table = self.table(self.table_width);
+        table.set_header(
+            ["Time", "User", "Field", "Old", "New"]
+                .map(|h| Cell::new(h).add_attribute(Attribute::Bold)),
+        );
+        for entry in history {
+            table.add_row(vec![
+                Cell::new(format_time(entry.at)),
+                Cell::new(&entry.user),
+                Cell::new(&entry.field).fg(Color::Yellow),
+                Cell::new(history_value(&entry.old)),
+                Cell::new(history_value(&entry.new)),
+            ]);
+        }
+        self.write(format!("{}\n", table));
+    }
+
+    pub fn print_report(&mut self, report: &TimeReport) {
+        let from = report
+            .from
+            .map_or(String::from("the beginning"), format_date);
+        self.write(format!(
+            "Tracked {} from {} to {}\n",
+            format_duration(report.total).bold(),
+            from,
+            format_date(report.to)
+        ));
+
+        let sections = [
+            (
+                "Task",
+                report
+                    .tasks
+                    .iter()
+                    .map(|t| (format!("{} {}", t.id, t.name), t.seconds))
+                    .collect::<Vec<_>>(),
+            ),
+            (
+                "Tag",
+                report
+                    .tags
+                    .iter()
+                    .map(|t| {
+                        (
+                            t.tag
+                                .as_ref()
+                                .map_or(String::from("(no tag)"), |t| format!("+{}", t)),
+                            t.seconds,
+                        )
+                    })
+                    .collect(),
+            ),
+            (
+                "Day",
+                report
+                    .days
+                    .iter()
+                    .map(|d| (format_date(d.date), d.seconds))
+                    .collect(),
+            ),
+        ];
+        for (title, rows) in sections {
+            let mut table = self.table(self.info_width);
+            table.set_header(vec![
+                Cell::new(title).add_attribute(Attribute::Bold),
+                Cell::new("Time").add_attribute(Attribute::Bold),
+            ]);
+            for (label, seconds) in rows {
+                table.add_row(vec![
+                    Cell::new(label),
+                    Cell::new(format_duration(seconds)).set_alignment(CellAlignment::Right),
+                ]);
+            }
+            self.write(format!("{}\n", table));
+        }
     }
 }
 
-//TODO
-pub enum SortOrder {
-    Alphabetical,
-    ReverseAlphabetical,
-    Status,
-    ReverseStatus,
+/// How `print_table` arranges the tasks.
+pub enum TableLayout {
+    /// A single table.
+    Flat,
+    /// One table per project.
+    Grouped,
+    /// Subtasks indented under their parents.
+    Tree,
 }
@@ -0,0 +1,125 @@
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+
+use task::{
+    locate,
+    result::{Message, Result},
+    store::{self, SAVE_FILE_NAME},
+};
+
+use crate::config::Config;
+
+/// Where the tasks are kept, see [`locate::store_path`]. A store left next
+/// to the executable by older builds is moved to the data dir, which is
+/// reported in the returned message. With `--dry-run` it's used where it is.
+pub fn store_path(matches: &ArgMatches, config: &Config) -> Result<(PathBuf, Option<Message>)> {
+    let path = locate::store_path(
+        matches.value_of_os("file").map(Path::new),
+        matches.is_present("global"),
+        config.store.as_deref(),
+    )?;
+    if path != store::default_path()? {
+        return Ok((path, None));
+    }
+    match legacy_store(&path)? {
+        Some(legacy) if matches.is_present("dry-run") => Ok((legacy, None)),
+        Some(legacy) => {
+            move_legacy_store(&legacy, &path)?;
+            Ok((path.clone(), Some(Message::MovedStore(legacy, path))))
+        }
+        None => Ok((path, None)),
+    }
+}
+
+/// The `task.json` in the executable's directory, if there is one and no
+/// store exists at `path` yet.
+fn legacy_store(path: &Path) -> Result<Option<PathBuf>> {
+    let Some(exe_dir) = std::env::current_exe()?.parent().map(Path::to_path_buf) else {
+        return Ok(None);
+    };
+    let legacy = exe_dir.join(SAVE_FILE_NAME);
+    Ok((!path.exists() && legacy.is_file()).then_some(legacy))
+}
+
+/// Moves the `legacy` store and its backups to `path`.
+fn move_legacy_store(legacy: &Path, path: &Path) -> Result<()> {
+    let exe_dir = legacy.parent().unwrap();
+    let dir = path.parent().unwrap();
+    std::fs::create_dir_all(dir)?;
+    for entry in std::fs::read_dir(exe_dir)? {
+        let name = entry?.file_name();
+        let name_str = name.to_string_lossy();
+        let is_store = name_str == SAVE_FILE_NAME
+            || (name_str.starts_with(&format!("{}.", SAVE_FILE_NAME))
+                && !name_str.ends_with(".lock")
+                && !name_str.ends_with(".tmp"));
+        if is_store {
+            move_file(&exe_dir.join(&name), &dir.join(&name))?;
+        }
+    }
+    Ok(())
+}
+
+/// Renames, falling back to copying when the paths are on different
+/// filesystems.
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if std::fs::rename(from, to).is_err() {
+        std::fs::copy(from, to)?;
+        std::fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::get_app;
+
+    #[test]
+    fn file_flag_names_the_store() {
+        let matches = get_app().get_matches_from(["task", "--file", "work.json", "-t"]);
+        let (path, msg) = store_path(&matches, &Config::default()).unwrap();
+        assert_eq!(path, Path::new("work.json"));
+        assert!(msg.is_none());
+
+        // Global, so it's also accepted after a subcommand.
+        let matches = get_app().get_matches_from(["task", "report", "--file", "work.db"]);
+        let (path, _) = store_path(&matches, &Config::default()).unwrap();
+        assert_eq!(path, Path::new("work.db"));
+    }
+
+    #[test]
+    fn moves_the_legacy_store_and_its_backups() {
+        let exe_dir = tempfile::tempdir().unwrap();
+        let data_dir = tempfile::tempdir().unwrap();
+        let files = [
+            "task.json",
+            "task.json.1",
+            "task.json.lock",
+            "task.json.tmp",
+            "task",
+        ];
+        for name in files {
+            std::fs::write(exe_dir.path().join(name), name).unwrap();
+        }
+        let path = data_dir.path().join("task").join(SAVE_FILE_NAME);
+        move_legacy_store(&exe_dir.path().join(SAVE_FILE_NAME), &path).unwrap();
+
+        for name in files {
+            let moved = matches!(name, "task.json" | "task.json.1");
+            let target = path.with_file_name(name);
+            assert_eq!(target.is_file(), moved, "{}", name);
+            assert_eq!(exe_dir.path().join(name).is_file(), !moved, "{}", name);
+        }
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "task.json");
+    }
+
+    #[test]
+    fn only_a_missing_store_is_replaced_by_the_legacy_one() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SAVE_FILE_NAME);
+        std::fs::write(&path, "").unwrap();
+        assert_eq!(legacy_store(&path).unwrap(), None);
+    }
+}
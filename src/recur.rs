@@ -0,0 +1,180 @@
+use std::{fmt::Display, str::FromStr};
+
+use chrono::{Datelike, Days, NaiveDate, Weekday};
+use serde::{de, Deserialize, Deserializer};
+
+use crate::{
+    date::{end_of_month, parse_weekday},
+    result::{Error, SystemError},
+};
+
+/// When the next instance of a recurring task is due.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Recurrence {
+    /// Every day.
+    Daily,
+    /// Every week on the given weekdays, at least one.
+    Weekly(#[serde(deserialize_with = "weekdays")] Vec<Weekday>),
+    /// Every month on the given day, clamped to the last day of shorter months.
+    Monthly(#[serde(deserialize_with = "day_of_month")] u32),
+    /// The given number of days after the previous instance was completed.
+    After(u32),
+}
+
+impl Recurrence {
+    /// The due date of the instance following one that was due on `due`
+    /// and completed `today`. Instances never start out overdue. Fails if
+    /// the date would be past the last one chrono can represent.
+    pub fn next_due(&self, due: Option<NaiveDate>, today: NaiveDate) -> Result<NaiveDate, Error> {
+        let next = if let Recurrence::After(days) = self {
+            today.checked_add_days(Days::new(*days as u64))
+        } else {
+            let mut next = self.next_after(due.unwrap_or(today));
+            while let Some(date) = next.filter(|next| *next < today) {
+                next = self.next_after(date);
+            }
+            next
+        };
+        next.ok_or_else(|| SystemError::InvalidRecurrence(self.to_string()).into())
+    }
+
+    fn next_after(&self, date: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            Recurrence::Daily => date.succ_opt(),
+            Recurrence::Weekly(days) => {
+                let mut next = date.succ_opt()?;
+                while !days.contains(&next.weekday()) {
+                    next = next.succ_opt()?;
+                }
+                Some(next)
+            }
+            Recurrence::Monthly(day) => {
+                let in_month = |first: NaiveDate| {
+                    let last = end_of_month(first)?.day();
+                    first.with_day((*day).min(last))
+                };
+                let this_month = in_month(date.with_day(1)?)?;
+                if this_month > date {
+                    Some(this_month)
+                } else {
+                    in_month(end_of_month(date)?.succ_opt()?)
+                }
+            }
+            Recurrence::After(days) => date.checked_add_days(Days::new(*days as u64)),
+        }
+    }
+}
+
+/// An empty weekday list would never find its next date, so hand-edited
+/// saves can't have one either.
+fn weekdays<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Weekday>, D::Error> {
+    let days = Vec::<Weekday>::deserialize(deserializer)?;
+    if days.is_empty() {
+        return Err(de::Error::invalid_length(0, &"at least one weekday"));
+    }
+    Ok(days)
+}
+
+fn day_of_month<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let day = u32::deserialize(deserializer)?;
+    if !(1..=31).contains(&day) {
+        return Err(de::Error::invalid_value(
+            de::Unexpected::Unsigned(day.into()),
+            &"a day between 1 and 31",
+        ));
+    }
+    Ok(day)
+}
+
+impl Display for Recurrence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Recurrence::Daily => write!(f, "daily"),
+            Recurrence::Weekly(days) => {
+                let days: Vec<String> = days.iter().map(|d| d.to_string().to_lowercase()).collect();
+                write!(f, "weekly:{}", days.join(","))
+            }
+            Recurrence::Monthly(day) => write!(f, "monthly:{}", day),
+            Recurrence::After(days) => write!(f, "after:{}d", days),
+        }
+    }
+}
+
+impl FromStr for Recurrence {
+    type Err = Error;
+
+    /// Parses `daily`, `weekly:mon,thu`, `monthly:15` and `after:3d`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.trim().to_lowercase();
+        let (rule, arg) = match lower.split_once(':') {
+            Some((rule, arg)) => (rule, Some(arg)),
+            None => (lower.as_str(), None),
+        };
+        let recurrence = match (rule, arg) {
+            ("daily", None) => Some(Recurrence::Daily),
+            ("weekly", Some(days)) => days
+                .split(',')
+                .map(parse_weekday)
+                .collect::<Option<Vec<_>>>()
+                .map(Recurrence::Weekly),
+            ("monthly", Some(day)) => day
+                .parse()
+                .ok()
+                .filter(|day| (1..=31).contains(day))
+                .map(Recurrence::Monthly),
+            ("after", Some(days)) => days
+                .trim_end_matches('d')
+                .parse()
+                .ok()
+                .filter(|days| *days > 0)
+                .map(Recurrence::After),
+            _ => None,
+        };
+        recurrence.ok_or_else(|| SystemError::InvalidRecurrence(s.to_owned()).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn next_instances_are_never_overdue() {
+        let today = date(2024, 3, 6);
+        let weekly: Recurrence = "weekly:mon,thu".parse().unwrap();
+        assert_eq!(
+            weekly.next_due(Some(date(2024, 2, 1)), today).unwrap(),
+            date(2024, 3, 7)
+        );
+        let monthly = Recurrence::Monthly(31);
+        assert_eq!(
+            monthly.next_due(Some(date(2024, 1, 31)), today).unwrap(),
+            date(2024, 3, 31)
+        );
+        let after = Recurrence::After(3);
+        assert_eq!(
+            after.next_due(Some(date(2024, 1, 1)), today).unwrap(),
+            date(2024, 3, 9)
+        );
+    }
+
+    #[test]
+    fn fails_past_the_last_date() {
+        let after: Recurrence = "after:4294967295d".parse().unwrap();
+        assert!(after.next_due(None, date(2024, 3, 6)).is_err());
+        assert!(Recurrence::Daily.next_due(None, NaiveDate::MAX).is_err());
+    }
+
+    #[test]
+    fn rejects_saved_rules_that_never_recur() {
+        assert!(serde_json::from_str::<Recurrence>(r#"{"weekly": []}"#).is_err());
+        assert!(serde_json::from_str::<Recurrence>(r#"{"monthly": 0}"#).is_err());
+        let weekly = serde_json::from_str::<Recurrence>(r#"{"weekly": ["Mon"]}"#).unwrap();
+        assert_eq!(weekly, Recurrence::Weekly(vec![Weekday::Mon]));
+    }
+}
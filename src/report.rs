@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Days, Local, NaiveDate, TimeZone, Utc};
+
+use crate::{
+    result::{Result, SystemError},
+    task::Task,
+};
+
+/// Time tracked on tasks within a date range, summed per task, tag and day.
+#[derive(serde::Serialize, Debug)]
+pub struct TimeReport {
+    pub from: Option<NaiveDate>,
+    pub to: NaiveDate,
+    /// Total tracked seconds.
+    pub total: i64,
+    pub tasks: Vec<TaskTime>,
+    pub tags: Vec<TagTime>,
+    pub days: Vec<DayTime>,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct TaskTime {
+    pub id: u32,
+    pub name: String,
+    pub seconds: i64,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct TagTime {
+    /// `None` for time tracked on tasks without tags.
+    pub tag: Option<String>,
+    pub seconds: i64,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct DayTime {
+    pub date: NaiveDate,
+    pub seconds: i64,
+}
+
+impl TimeReport {
+    /// Sums the time tracked between the start of `from` (or the beginning
+    /// of time) and the end of `to`, both local dates. Intervals that are
+    /// still open count up to `now`. Fails if `from` comes after `to`.
+    pub fn new(
+        content: &[Task],
+        from: Option<NaiveDate>,
+        to: NaiveDate,
+        now: DateTime<Utc>,
+    ) -> Result<Self> {
+        if let Some(from) = from.filter(|&from| from > to) {
+            return Err(SystemError::ReversedRange(from, to).into());
+        }
+        let range_start = from.map(local_midnight);
+        let range_end = local_midnight(to + Days::new(1)).min(now);
+
+        let mut tasks = Vec::new();
+        let mut tags: BTreeMap<Option<String>, i64> = BTreeMap::new();
+        let mut days: BTreeMap<NaiveDate, i64> = BTreeMap::new();
+        for task in content {
+            let mut task_seconds = 0;
+            for interval in &task.intervals {
+                let start = range_start.map_or(interval.start, |s| interval.start.max(s));
+                let end = interval.end.unwrap_or(now).min(range_end);
+                for (date, seconds) in split_days(start, end) {
+                    *days.entry(date).or_default() += seconds;
+                    task_seconds += seconds;
+                }
+            }
+            if task_seconds == 0 {
+                continue;
+            }
+            if task.tags.is_empty() {
+                *tags.entry(None).or_default() += task_seconds;
+            }
+            for tag in &task.tags {
+                *tags.entry(Some(tag.clone())).or_default() += task_seconds;
+            }
+            tasks.push(TaskTime {
+                id: task.id,
+                name: task.name.clone(),
+                seconds: task_seconds,
+            });
+        }
+        tasks.sort_by(|a, b| b.seconds.cmp(&a.seconds).then(a.id.cmp(&b.id)));
+
+        Ok(Self {
+            from,
+            to,
+            total: tasks.iter().map(|t| t.seconds).sum(),
+            tasks,
+            tags: tags
+                .into_iter()
+                .map(|(tag, seconds)| TagTime { tag, seconds })
+                .collect(),
+            days: days
+                .into_iter()
+                .map(|(date, seconds)| DayTime { date, seconds })
+                .collect(),
+        })
+    }
+}
+
+/// Formats seconds as hours and minutes, `1h 05m`.
+pub fn format_duration(seconds: i64) -> String {
+    format!("{}h {:02}m", seconds / 3600, seconds % 3600 / 60)
+}
+
+fn local_midnight(date: NaiveDate) -> DateTime<Utc> {
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap();
+    Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .map_or_else(|| midnight.and_utc(), |t| t.with_timezone(&Utc))
+}
+
+/// Splits a span of time at local midnights, returning the seconds spent
+/// on each day. Days with less than a second are left out.
+fn split_days(start: DateTime<Utc>, end: DateTime<Utc>) -> Vec<(NaiveDate, i64)> {
+    let mut days = Vec::new();
+    let mut cursor = start;
+    while cursor < end {
+        let date = cursor.with_timezone(&Local).date_naive();
+        let next = local_midnight(date + Days::new(1)).min(end);
+        let seconds = (next - cursor).num_seconds();
+        if seconds > 0 {
+            days.push((date, seconds));
+        }
+        cursor = next;
+    }
+    days
+}
@@ -1,7 +1,10 @@
-use std::fmt::Display;
+use std::{fmt::Display, path::PathBuf, time::Duration};
 
+use chrono::NaiveDate;
 use colored::Colorize;
 
+use crate::date::format_date;
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug)]
@@ -13,6 +16,11 @@ impl Error {
     pub fn new(err: ErrorType) -> Self {
         Self { err: Box::new(err) }
     }
+
+    /// What went wrong, for callers that handle some errors themselves.
+    pub fn kind(&self) -> &ErrorType {
+        &self.err
+    }
 }
 
 impl Display for Error {
@@ -24,15 +32,40 @@ impl Display for Error {
 impl std::error::Error for Error {}
 
 pub enum Message {
-    CreatedTask(String),
-    DeletedTasks(Vec<String>, Vec<String>),
+    CreatedTask(String, u32),
+    DeletedTasks(Vec<String>),
     AppliedTaskChanges(String),
+    /// Name, short ID and due date of the next instance of a recurring task.
+    Recurred(String, u32, NaiveDate),
+    Annotated(String),
+    /// Task name and the number of the removed note.
+    Denotated(String, usize),
+    /// The undone command and how many tasks it changed.
+    Undone(String, usize),
+    /// The redone command and how many tasks it changed.
+    Redone(String, usize),
+    /// Number of the restored backup.
+    Restored(usize),
+    /// Setting and its new value.
+    ConfigSet(String, String),
+    /// Old and new location of a store moved out of the executable's directory.
+    MovedStore(PathBuf, PathBuf),
+    /// Path of the new project store.
+    InitializedStore(PathBuf),
+    /// Number of tasks, the new store and where the old one was moved.
+    MigratedStore(usize, PathBuf, PathBuf),
+    /// `--dry-run` kept the changes from being saved.
+    DryRun,
 }
 
 #[derive(Debug)]
 pub enum ErrorType {
     System(SystemError),
     Serde(SerdeError),
+    Migration(MigrationError),
+    Lock(LockError),
+    Config(ConfigError),
+    Database(DatabaseError),
     Io(std::io::Error),
 }
 
@@ -40,56 +73,107 @@ pub enum ErrorType {
 pub enum SystemError {
     TaskAlreadyExists(String),
     TaskDoesntExist(String),
+    InvalidStatus(String),
+    InvalidName(String),
+    InvalidDate(String),
+    InvalidPriority(String),
+    InvalidTag(String),
+    InvalidProject(String),
+    AmbiguousTask(String),
+    ParentCycle(String),
+    /// Task name and the number of its open subtasks.
+    OpenSubtasks(String, usize),
+    /// The task and the dependency that would close the cycle.
+    DependencyCycle(String, String),
+    InvalidRecurrence(String),
+    /// Why the filter expression couldn't be parsed.
+    InvalidFilter(String),
+    /// No task was named and the filter, if any, matched none.
+    NoMatches,
+    /// The user declined a bulk change.
+    Aborted,
+    /// A command that can't be run with `--dry-run`.
+    NoDryRun(String),
+    /// `--rename` was given with this many tasks selected.
+    RenameMany(usize),
+    BackupDoesntExist(usize),
+    NoDataDir,
+    NoConfigDir,
+    /// `task init` found a project store already in place.
+    StoreExists(PathBuf),
+    /// `migrate-store` was asked for the format the store already has.
+    SameBackend(PathBuf),
+    /// The store asked for and the one it was migrated to.
+    StoreMigrated(PathBuf, PathBuf),
+    NothingToUndo,
+    NothingToRedo,
+    EmptyNote,
+    /// Task name and the note number that was asked for.
+    NoteDoesntExist(String, usize),
+    /// A report range whose first day comes after its last: (from, to).
+    ReversedRange(NaiveDate, NaiveDate),
     Empty,
 }
 
+#[derive(Debug)]
+pub enum MigrationError {
+    /// The save was written by a newer version: (found, supported).
+    UnsupportedVersion(u32, u32),
+}
+
+#[derive(Debug)]
+pub enum LockError {
+    /// Another run held the store's lock for longer than the timeout.
+    Timeout(PathBuf, Duration),
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The config file and what's wrong with it.
+    Parse(PathBuf, String),
+    UnknownKey(String),
+    /// The key and why its value isn't accepted.
+    InvalidValue(String, String),
+}
+
 #[derive(Debug)]
 pub enum SerdeError {
     Serialization(serde_json::Error),
 }
 
+/// An error from the SQLite store, only shown, so the library it comes
+/// from stays out of the API.
+#[derive(Debug)]
+pub struct DatabaseError(rusqlite::Error);
+
+impl Display for DatabaseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
 impl Display for Message {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}{}", "Info".bright_green(), "~ ".bright_blue())?;
         match self {
-            Message::CreatedTask(msg) => writeln!(
+            Message::CreatedTask(msg, id) => writeln!(
                 f,
-                "Created task {}{}{}.",
+                "Created task {}{}{} with ID {}.",
                 "\"".yellow(),
                 msg.yellow(),
-                "\"".yellow()
+                "\"".yellow(),
+                id.to_string().yellow()
             ),
-            Message::DeletedTasks(msgs, errs) => {
-                if !msgs.is_empty() {
-                    write!(f, "Deleted: ")?;
-                    let mut iter = msgs.iter().peekable();
-                    loop {
-                        match iter.next() {
-                            Some(msg) => write!(f, "{}", msg.yellow())?,
-                            None => break,
-                        }
-                        if iter.peek().is_some() {
-                            write!(f, ", ")?;
-                        }
+            Message::DeletedTasks(msgs) => {
+                write!(f, "Deleted: ")?;
+                let mut iter = msgs.iter().peekable();
+                while let Some(msg) = iter.next() {
+                    write!(f, "{}", msg.yellow())?;
+                    if iter.peek().is_some() {
+                        write!(f, ", ")?;
                     }
-                    writeln!(f, ".")?;
                 }
-
-                if !errs.is_empty() {
-                    write!(f, "Failed to delete ")?;
-                    let mut iter = errs.iter().peekable();
-                    loop {
-                        match iter.next() {
-                            Some(msg) => write!(f, "{}", msg.yellow())?,
-                            None => break,
-                        }
-                        if iter.peek().is_some() {
-                            write!(f, ", ")?;
-                        }
-                    }
-                    writeln!(f, ".")?;
-                }
-                Ok(())
+                writeln!(f, ".")
             }
             Message::AppliedTaskChanges(msg) => writeln!(
                 f,
@@ -98,6 +182,74 @@ impl Display for Message {
                 msg.yellow(),
                 "\"".yellow()
             ),
+            Message::Recurred(msg, id, due) => writeln!(
+                f,
+                "Completed {}{}{}, the next instance has ID {} and is due {}.",
+                "\"".yellow(),
+                msg.yellow(),
+                "\"".yellow(),
+                id.to_string().yellow(),
+                format_date(*due).yellow()
+            ),
+            Message::Undone(command, count) => writeln!(
+                f,
+                "Undid {}{}{}, restoring {} tasks.",
+                "\"".yellow(),
+                command.yellow(),
+                "\"".yellow(),
+                count.to_string().yellow()
+            ),
+            Message::Redone(command, count) => writeln!(
+                f,
+                "Redid {}{}{}, changing {} tasks.",
+                "\"".yellow(),
+                command.yellow(),
+                "\"".yellow(),
+                count.to_string().yellow()
+            ),
+            Message::Restored(number) => writeln!(
+                f,
+                "Restored backup {}, the previous tasks are now backup {}.",
+                number.to_string().yellow(),
+                "1".yellow()
+            ),
+            Message::ConfigSet(key, value) => {
+                writeln!(f, "Set {} to {}.", key.yellow(), value.yellow())
+            }
+            Message::MovedStore(from, to) => writeln!(
+                f,
+                "Moved the tasks from {} to {}.",
+                from.display().to_string().yellow(),
+                to.display().to_string().yellow()
+            ),
+            Message::InitializedStore(path) => writeln!(
+                f,
+                "Created a task store for this directory in {}.",
+                path.display().to_string().yellow()
+            ),
+            Message::MigratedStore(count, to, old) => writeln!(
+                f,
+                "Copied {} tasks to {}, the old store was renamed to {}.",
+                count.to_string().yellow(),
+                to.display().to_string().yellow(),
+                old.display().to_string().yellow()
+            ),
+            Message::DryRun => writeln!(f, "This was a dry run, nothing was saved."),
+            Message::Annotated(msg) => writeln!(
+                f,
+                "Added a note to {}{}{}.",
+                "\"".yellow(),
+                msg.yellow(),
+                "\"".yellow()
+            ),
+            Message::Denotated(msg, number) => writeln!(
+                f,
+                "Removed note {} from {}{}{}.",
+                number.to_string().yellow(),
+                "\"".yellow(),
+                msg.yellow(),
+                "\"".yellow()
+            ),
         }
     }
 }
@@ -122,6 +274,166 @@ impl Display for ErrorType {
                         e.yellow(),
                         "\"".yellow()
                     ),
+                    SystemError::InvalidStatus(e) => writeln!(
+                        f,
+                        "{}{}{} is not a valid status. Use one of: {}.",
+                        "\"".yellow(),
+                        e.yellow(),
+                        "\"".yellow(),
+                        "f, a, s, c".yellow()
+                    ),
+                    SystemError::InvalidName(e) => writeln!(
+                        f,
+                        "{}{}{} can't be used as a task name, names can't be empty, numbers or UUIDs or contain '/' or ','.",
+                        "\"".yellow(),
+                        e.yellow(),
+                        "\"".yellow()
+                    ),
+                    SystemError::InvalidDate(e) => writeln!(
+                        f,
+                        "{}{}{} is not a valid date. Use YYYY-MM-DD, today, tomorrow, a weekday, +Nd/+Nw/+Nm, eow or eom.",
+                        "\"".yellow(),
+                        e.yellow(),
+                        "\"".yellow()
+                    ),
+                    SystemError::InvalidPriority(e) => writeln!(
+                        f,
+                        "{}{}{} is not a valid priority. Use one of: {}.",
+                        "\"".yellow(),
+                        e.yellow(),
+                        "\"".yellow(),
+                        "H, M, L, none".yellow()
+                    ),
+                    SystemError::InvalidTag(e) => writeln!(
+                        f,
+                        "{}{}{} is not a valid tag. Add tags with {} and remove them with {}, tags can't contain spaces or commas.",
+                        "\"".yellow(),
+                        e.yellow(),
+                        "\"".yellow(),
+                        "+tag".yellow(),
+                        "-tag".yellow()
+                    ),
+                    SystemError::InvalidProject(e) => writeln!(
+                        f,
+                        "{}{}{} is not a valid project. Projects are dot separated names like {}.",
+                        "\"".yellow(),
+                        e.yellow(),
+                        "\"".yellow(),
+                        "work.backend".yellow()
+                    ),
+                    SystemError::AmbiguousTask(e) => writeln!(
+                        f,
+                        "There are several tasks named {}{}{}, use its ID or {}.",
+                        "\"".yellow(),
+                        e.yellow(),
+                        "\"".yellow(),
+                        "project/name".yellow()
+                    ),
+                    SystemError::ParentCycle(e) => writeln!(
+                        f,
+                        "Task {}{}{} can't be a subtask of itself or of its own subtasks.",
+                        "\"".yellow(),
+                        e.yellow(),
+                        "\"".yellow()
+                    ),
+                    SystemError::OpenSubtasks(e, count) => writeln!(
+                        f,
+                        "Task {}{}{} has {} open subtasks, complete them first or use {}.",
+                        "\"".yellow(),
+                        e.yellow(),
+                        "\"".yellow(),
+                        count.to_string().yellow(),
+                        "--cascade".yellow()
+                    ),
+                    SystemError::DependencyCycle(task, dependency) => writeln!(
+                        f,
+                        "Task {}{}{} can't depend on {}{}{}, it would create a dependency cycle.",
+                        "\"".yellow(),
+                        task.yellow(),
+                        "\"".yellow(),
+                        "\"".yellow(),
+                        dependency.yellow(),
+                        "\"".yellow()
+                    ),
+                    SystemError::InvalidRecurrence(e) => writeln!(
+                        f,
+                        "{}{}{} is not a valid recurrence. Use daily, weekly:mon,thu, monthly:15, after:3d or none.",
+                        "\"".yellow(),
+                        e.yellow(),
+                        "\"".yellow()
+                    ),
+                    SystemError::InvalidFilter(e) => writeln!(
+                        f,
+                        "Invalid filter, {}. Filters look like {}.",
+                        e.yellow(),
+                        "status:active and (+urgent or due.before:fri)".yellow()
+                    ),
+                    SystemError::NoMatches => writeln!(
+                        f,
+                        "No tasks were selected. Name them or use a {} that matches some.",
+                        "--filter".yellow()
+                    ),
+                    SystemError::Aborted => writeln!(f, "Nothing was changed."),
+                    SystemError::NoDryRun(command) => writeln!(
+                        f,
+                        "{} can't be used with {}, it would change files anyway.",
+                        command.yellow(),
+                        "--dry-run".yellow()
+                    ),
+                    SystemError::RenameMany(count) => writeln!(
+                        f,
+                        "Only one task can be renamed at a time, {} were selected.",
+                        count.to_string().yellow()
+                    ),
+                    SystemError::BackupDoesntExist(number) => writeln!(
+                        f,
+                        "There is no backup {}, list them with {}.",
+                        number.to_string().yellow(),
+                        "'restore'".yellow()
+                    ),
+                    SystemError::NoDataDir => writeln!(
+                        f,
+                        "Couldn't find a data directory for the tasks, set {} or use {}.",
+                        "TASK_FILE".yellow(),
+                        "--file".yellow()
+                    ),
+                    SystemError::NoConfigDir => {
+                        writeln!(f, "Couldn't find a config directory to write to.")
+                    }
+                    SystemError::StoreExists(path) => writeln!(
+                        f,
+                        "There already is a task store in {}.",
+                        path.display().to_string().yellow()
+                    ),
+                    SystemError::StoreMigrated(path, target) => writeln!(
+                        f,
+                        "{} was migrated to {}, use that store instead.",
+                        path.display().to_string().yellow(),
+                        target.display().to_string().yellow()
+                    ),
+                    SystemError::SameBackend(path) => writeln!(
+                        f,
+                        "{} already is a store of that format.",
+                        path.display().to_string().yellow()
+                    ),
+                    SystemError::NothingToUndo => writeln!(f, "There is nothing to undo."),
+                    SystemError::NothingToRedo => writeln!(f, "There is nothing to redo."),
+                    SystemError::EmptyNote => writeln!(f, "Notes can't be empty."),
+                    SystemError::NoteDoesntExist(e, number) => writeln!(
+                        f,
+                        "Task {}{}{} has no note {}, see its notes with {}.",
+                        "\"".yellow(),
+                        e.yellow(),
+                        "\"".yellow(),
+                        number.to_string().yellow(),
+                        "--info".yellow()
+                    ),
+                    SystemError::ReversedRange(from, to) => writeln!(
+                        f,
+                        "The report can't start on {} after it ends on {}.",
+                        format_date(*from).yellow(),
+                        format_date(*to).yellow()
+                    ),
                     SystemError::Empty => writeln!(
                         f,
                         "The task table is empty. Create some tasks with {} command!",
@@ -135,6 +447,52 @@ impl Display for ErrorType {
                     SerdeError::Serialization(e) => writeln!(f, "{}", e),
                 }
             }
+            ErrorType::Migration(err) => {
+                write!(f, "{}{}", "Migration Error".red(), "~ ".bright_blue())?;
+                match err {
+                    MigrationError::UnsupportedVersion(found, supported) => writeln!(
+                        f,
+                        "The save file has version {} but this build only supports up to version {}. Update the app to open it.",
+                        found.to_string().yellow(),
+                        supported.to_string().yellow()
+                    ),
+                }
+            }
+            ErrorType::Lock(err) => {
+                write!(f, "{}{}", "Lock Error".red(), "~ ".bright_blue())?;
+                match err {
+                    LockError::Timeout(path, timeout) => writeln!(
+                        f,
+                        "{} is in use by another task process, gave up after {}s. Try again or raise {}.",
+                        path.display().to_string().yellow(),
+                        timeout.as_secs_f32().to_string().yellow(),
+                        "--lock-timeout".yellow()
+                    ),
+                }
+            }
+            ErrorType::Config(err) => {
+                write!(f, "{}{}", "Config Error".red(), "~ ".bright_blue())?;
+                match err {
+                    ConfigError::Parse(path, e) => writeln!(
+                        f,
+                        "Couldn't read {}: {}",
+                        path.display().to_string().yellow(),
+                        e
+                    ),
+                    ConfigError::UnknownKey(key) => writeln!(
+                        f,
+                        "There is no setting {}, see them all with {}.",
+                        key.yellow(),
+                        "'config list'".yellow()
+                    ),
+                    ConfigError::InvalidValue(key, reason) => {
+                        writeln!(f, "Invalid {}, {}.", key.yellow(), reason)
+                    }
+                }
+            }
+            ErrorType::Database(err) => {
+                writeln!(f, "{}{}{}", "Database Error".red(), "~ ".bright_blue(), err)
+            }
             ErrorType::Io(err) => {
                 writeln!(f, "{}{}{}", "Io Error".red(), "~ ".bright_blue(), err)
             }
@@ -148,12 +506,36 @@ impl From<SystemError> for Error {
     }
 }
 
+impl From<MigrationError> for Error {
+    fn from(e: MigrationError) -> Self {
+        Error::new(ErrorType::Migration(e))
+    }
+}
+
+impl From<LockError> for Error {
+    fn from(e: LockError) -> Self {
+        Error::new(ErrorType::Lock(e))
+    }
+}
+
+impl From<ConfigError> for Error {
+    fn from(e: ConfigError) -> Self {
+        Error::new(ErrorType::Config(e))
+    }
+}
+
 impl From<serde_json::Error> for Error {
     fn from(e: serde_json::Error) -> Self {
         Error::new(ErrorType::Serde(SerdeError::Serialization(e)))
     }
 }
 
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Error::new(ErrorType::Database(DatabaseError(e)))
+    }
+}
+
 impl From<std::io::Error> for Error {
     fn from(e: std::io::Error) -> Self {
         Error::new(ErrorType::Io(e))
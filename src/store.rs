@@ -0,0 +1,620 @@
+use std::{
+    collections::BTreeMap,
+    fs::TryLockError,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::{
+    handler::{HistoryEntry, Journal, TaskProperties},
+    result::*,
+    task::Status,
+};
+
+mod json;
+mod memory;
+mod sqlite;
+
+pub use json::JsonStore;
+pub use memory::MemoryStore;
+pub use sqlite::SqliteStore;
+
+pub const SAVE_FILE_NAME: &str = "task.json";
+
+/// Name of a directory's store after `migrate-store --to sqlite`.
+pub const DB_FILE_NAME: &str = "task.db";
+
+/// Values of `migrate-store --to`.
+pub const BACKENDS: [&str; 2] = ["json", "sqlite"];
+
+/// How many earlier versions of the store are kept as `task.json.1` (the
+/// newest) up to `task.json.5`.
+const BACKUP_COUNT: usize = 5;
+
+/// Upgrade steps for the save format, `MIGRATIONS[n]` upgrades a save
+/// from version `n` to version `n + 1`.
+const MIGRATIONS: &[fn(&mut Value, DateTime<Utc>)] = &[
+    migrate_v0_to_v1,
+    migrate_v1_to_v2,
+    migrate_v2_to_v3,
+    no_changes, // due dates
+    no_changes, // priorities
+    no_changes, // tags
+    no_changes, // projects
+    no_changes, // subtasks
+    no_changes, // dependencies
+    no_changes, // recurrence
+    no_changes, // time tracking
+    no_changes, // annotations
+    no_changes, // undo journal
+    no_changes, // change history
+];
+
+/// Version of the save format written by this build.
+pub const SAVE_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// How long to wait between attempts to lock the store.
+const LOCK_RETRY: Duration = Duration::from_millis(50);
+
+/// Where the tasks are kept between runs.
+///
+/// The handler reads tasks by ID or through a [`Query`] and hands back
+/// every task it changed before calling `save`, so a store doesn't have to
+/// read all tasks up front. Writes only have to be durable once `save`
+/// returns.
+pub trait TaskStore {
+    /// Reads the state every run needs besides the tasks.
+    fn load(&mut self) -> Result<StoreMeta>;
+    fn get(&self, id: u32) -> Result<Option<TaskProperties>>;
+    fn insert(&mut self, id: u32, task: &TaskProperties) -> Result<()>;
+    fn update(&mut self, id: u32, task: &TaskProperties) -> Result<()>;
+    fn delete(&mut self, id: u32) -> Result<()>;
+    /// The tasks matching `query`, ordered by ID.
+    fn query(&self, query: &Query) -> Result<Vec<(u32, TaskProperties)>>;
+    /// The change history of a task, oldest first.
+    fn history(&self, uuid: Uuid) -> Result<Vec<HistoryEntry>>;
+    fn add_history(&mut self, uuid: Uuid, entries: &[HistoryEntry]) -> Result<()>;
+    /// Makes every change so far durable, keeping the previous state as
+    /// the newest backup.
+    fn save(&mut self, meta: &StoreMeta) -> Result<()>;
+    /// Everything in the store, for copying it elsewhere.
+    fn snapshot(&self) -> Result<Snapshot>;
+    /// Replaces everything in the store with `snapshot`.
+    fn replace(&mut self, snapshot: Snapshot) -> Result<()>;
+    /// The backups of earlier saves, newest first.
+    fn backups(&self) -> Vec<Backup>;
+    fn read_backup(&self, number: usize) -> Result<Snapshot>;
+}
+
+/// State kept next to the tasks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoreMeta {
+    /// Short ID handed out to the next created task, IDs are never reused.
+    pub next_id: u32,
+    pub journal: Journal,
+}
+
+/// Everything in a store, laid out like the JSON save.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+pub struct Snapshot {
+    pub version: u32,
+    pub next_id: u32,
+    pub tasks: BTreeMap<u32, TaskProperties>,
+    #[serde(default, skip_serializing_if = "Journal::is_empty")]
+    pub journal: Journal,
+    /// Every change ever made to each task, kept after the task is deleted.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub history: BTreeMap<Uuid, Vec<HistoryEntry>>,
+}
+
+impl Default for Snapshot {
+    fn default() -> Self {
+        Self {
+            version: SAVE_VERSION,
+            next_id: 1,
+            tasks: BTreeMap::new(),
+            journal: Journal::default(),
+            history: BTreeMap::new(),
+        }
+    }
+}
+
+impl Snapshot {
+    fn meta(&self) -> StoreMeta {
+        StoreMeta {
+            next_id: self.next_id,
+            journal: self.journal.clone(),
+        }
+    }
+}
+
+/// Selects tasks in a store, fields left empty match every task.
+#[derive(Debug, Default, Clone)]
+pub struct Query {
+    pub name: Option<String>,
+    /// `Some(None)` matches tasks outside of any project, subprojects
+    /// don't match.
+    pub project: Option<Option<String>>,
+    pub uuid: Option<Uuid>,
+    /// Direct subtasks of this task.
+    pub parent: Option<u32>,
+    /// Tasks waiting on this task.
+    pub depends_on: Option<u32>,
+    /// Tasks in any of these statuses.
+    pub statuses: Vec<Status>,
+    /// Tasks with all of these tags.
+    pub tags: Vec<String>,
+    pub due_before: Option<NaiveDate>,
+    pub due_after: Option<NaiveDate>,
+    pub archived: Option<bool>,
+    pub limit: Option<usize>,
+}
+
+impl Query {
+    /// Whether a task is selected, ignoring the limit.
+    pub fn matches(&self, task: &TaskProperties) -> bool {
+        self.name.as_ref().is_none_or(|name| task.name == *name)
+            && self.project.as_ref().is_none_or(|p| task.project == *p)
+            && self.uuid.is_none_or(|uuid| task.uuid == uuid)
+            && self.parent.is_none_or(|parent| task.parent == Some(parent))
+            && self.depends_on.is_none_or(|id| task.depends.contains(&id))
+            && (self.statuses.is_empty() || self.statuses.contains(&task.status))
+            && self.tags.iter().all(|tag| task.tags.contains(tag))
+            && self
+                .due_before
+                .is_none_or(|date| task.due.is_some_and(|due| due < date))
+            && self
+                .due_after
+                .is_none_or(|date| task.due.is_some_and(|due| due > date))
+            && self
+                .archived
+                .is_none_or(|archived| task.archived == archived)
+    }
+}
+
+/// An earlier version of the store, `task.json.<number>`.
+pub struct Backup {
+    pub number: usize,
+    pub saved_at: DateTime<Utc>,
+    /// `None` when the backup can't be read.
+    pub tasks: Option<usize>,
+}
+
+/// The formats a store can be kept in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    Json,
+    Sqlite,
+}
+
+impl Backend {
+    /// One of [`BACKENDS`].
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(Self::Json),
+            "sqlite" => Some(Self::Sqlite),
+            _ => None,
+        }
+    }
+
+    /// SQLite for `.db`, `.sqlite` and `.sqlite3` files, JSON otherwise.
+    pub fn of(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("db" | "sqlite" | "sqlite3") => Self::Sqlite,
+            _ => Self::Json,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Sqlite => "db",
+        }
+    }
+}
+
+/// Opens the store at `path` in the format its extension names, creating
+/// it if needed. Waits up to `lock_timeout` for other runs using the same
+/// store to finish. A store that was migrated away isn't created again, that
+/// would quietly start over with no tasks. Saves upgraded from before tasks
+/// had timestamps get `now`.
+pub fn open(path: &Path, lock_timeout: Duration, now: DateTime<Utc>) -> Result<Box<dyn TaskStore>> {
+    check_migrated(path)?;
+    open_as(path, Backend::of(path), lock_timeout, now)
+}
+
+/// Reads everything in the store at `path` without creating, locking or
+/// upgrading anything on disk, a missing store reads as an empty one.
+pub fn read(path: &Path, now: DateTime<Utc>) -> Result<Snapshot> {
+    check_migrated(path)?;
+    if !path.exists() {
+        return Ok(Snapshot::default());
+    }
+    match Backend::of(path) {
+        Backend::Json => json::read(path, now),
+        Backend::Sqlite => sqlite::read(path, now),
+    }
+}
+
+fn check_migrated(path: &Path) -> Result<()> {
+    if !path.exists() && with_suffix(path, ".migrated").exists() {
+        let other = match Backend::of(path) {
+            Backend::Json => Backend::Sqlite,
+            Backend::Sqlite => Backend::Json,
+        };
+        let target = path.with_extension(other.extension());
+        return Err(SystemError::StoreMigrated(path.to_path_buf(), target).into());
+    }
+    Ok(())
+}
+
+fn open_as(
+    path: &Path,
+    backend: Backend,
+    lock_timeout: Duration,
+    now: DateTime<Utc>,
+) -> Result<Box<dyn TaskStore>> {
+    Ok(match backend {
+        Backend::Json => Box::new(JsonStore::open(path, lock_timeout, now)?),
+        Backend::Sqlite => Box::new(SqliteStore::open(path, lock_timeout, now)?),
+    })
+}
+
+/// The store in `dir`, the SQLite one once the directory was migrated.
+pub fn in_dir(dir: &Path) -> PathBuf {
+    let db = dir.join(DB_FILE_NAME);
+    if db.is_file() {
+        db
+    } else {
+        dir.join(SAVE_FILE_NAME)
+    }
+}
+
+/// The user's own store in their data dir, `$XDG_DATA_HOME/task/` on Linux.
+/// Project stores and the CLI's `--file` and `store` setting aren't looked at.
+pub fn default_path() -> Result<PathBuf> {
+    let mut dir = dirs::data_dir().ok_or(SystemError::NoDataDir)?;
+    dir.push("task");
+    Ok(in_dir(&dir))
+}
+
+/// Copies the store at `path` into a new `to` store next to it. The old
+/// store is renamed to `<name>.migrated` so it isn't picked up anymore.
+pub fn migrate(
+    path: &Path,
+    to: Backend,
+    lock_timeout: Duration,
+    now: DateTime<Utc>,
+) -> Result<Message> {
+    if Backend::of(path) == to {
+        return Err(SystemError::SameBackend(path.to_path_buf()).into());
+    }
+    let target = path.with_extension(to.extension());
+    if target.exists() {
+        return Err(SystemError::StoreExists(target).into());
+    }
+    let source = open(path, lock_timeout, now)?;
+    let snapshot = source.snapshot()?;
+    let tasks = snapshot.tasks.len();
+    // Migrating back leaves the first migration's `.migrated` file behind.
+    let mut store = open_as(&target, to, lock_timeout, now)?;
+    let meta = snapshot.meta();
+    store.replace(snapshot)?;
+    store.save(&meta)?;
+
+    let old = with_suffix(path, ".migrated");
+    std::fs::rename(path, &old)?;
+    Ok(Message::MigratedStore(tasks, target, old))
+}
+
+/// Upgrades a save in the JSON layout to [`SAVE_VERSION`], returning the
+/// version it had. `now` is when the upgrade happens.
+fn upgrade(value: &mut Value, now: DateTime<Utc>) -> Result<u32> {
+    // Saves from before versioning was introduced have no version field.
+    let version = value.get("version").and_then(Value::as_u64).unwrap_or(0) as u32;
+    if version > SAVE_VERSION {
+        return Err(MigrationError::UnsupportedVersion(version, SAVE_VERSION).into());
+    }
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migration(value, now);
+        value["version"] = Value::from(from as u32 + 1);
+    }
+    Ok(version)
+}
+
+/// Normalizes the status strings written before `Status` was stored
+/// directly (short codes and the full names passed by the flags).
+fn migrate_v0_to_v1(value: &mut Value, _: DateTime<Utc>) {
+    if let Some(tasks) = value.get_mut("tasks").and_then(Value::as_object_mut) {
+        for task in tasks.values_mut() {
+            let status = task
+                .get("status")
+                .and_then(Value::as_str)
+                .and_then(|s| s.parse::<Status>().ok())
+                .unwrap_or_default();
+            task["status"] = serde_json::to_value(status).unwrap();
+        }
+    }
+}
+
+/// Re-keys the task map from names to short IDs, moving the name into the
+/// task and giving every task a UUID.
+fn migrate_v1_to_v2(value: &mut Value, _: DateTime<Utc>) {
+    let mut tasks: Vec<(String, Value)> = match value.get_mut("tasks").map(Value::take) {
+        Some(Value::Object(tasks)) => tasks.into_iter().collect(),
+        _ => Vec::new(),
+    };
+    tasks.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut by_id = serde_json::Map::new();
+    let mut next_id = 1;
+    for (name, mut task) in tasks {
+        task["name"] = Value::from(name);
+        task["uuid"] = Value::from(Uuid::new_v4().to_string());
+        by_id.insert(next_id.to_string(), task);
+        next_id += 1;
+    }
+    value["tasks"] = Value::Object(by_id);
+    value["next_id"] = Value::from(next_id);
+}
+
+/// Used for versions that only add optional fields. The version still has to
+/// be bumped so older builds refuse the save instead of dropping the fields.
+fn no_changes(_: &mut Value, _: DateTime<Utc>) {}
+
+/// Older saves have no timestamps, the time of the migration is the best
+/// estimate available for when those tasks were created.
+fn migrate_v2_to_v3(value: &mut Value, now: DateTime<Utc>) {
+    let now = Value::from(now.to_rfc3339());
+    if let Some(tasks) = value.get_mut("tasks").and_then(Value::as_object_mut) {
+        for task in tasks.values_mut() {
+            task["created_at"] = now.clone();
+            task["modified_at"] = now.clone();
+        }
+    }
+}
+
+/// `path` with `suffix` appended, like `task.json.lock`.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+fn backup_path(path: &Path, number: usize) -> PathBuf {
+    with_suffix(path, &format!(".{}", number))
+}
+
+/// Shifts every backup of the store at `path` one number up, dropping the
+/// oldest, and copies the store to backup 1.
+fn rotate_backups(path: &Path) -> Result<()> {
+    if !path.is_file() {
+        return Ok(());
+    }
+    for number in (1..BACKUP_COUNT).rev() {
+        let backup = backup_path(path, number);
+        if backup.is_file() {
+            std::fs::rename(&backup, backup_path(path, number + 1))?;
+        }
+    }
+    std::fs::copy(path, backup_path(path, 1))?;
+    Ok(())
+}
+
+/// The backups of the store at `path` on disk, `count` reads how many
+/// tasks one holds.
+fn list_backups(path: &Path, count: impl Fn(&Path) -> Option<usize>) -> Vec<Backup> {
+    (1..=BACKUP_COUNT)
+        .filter_map(|number| {
+            let path = backup_path(path, number);
+            let saved_at = std::fs::metadata(&path).ok()?.modified().ok()?;
+            Some(Backup {
+                number,
+                saved_at: saved_at.into(),
+                tasks: count(&path),
+            })
+        })
+        .collect()
+}
+
+/// Creates the directory a new store goes into.
+fn create_dir(path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    Ok(())
+}
+
+/// Takes an exclusive lock on `task.json.lock` next to the store, retrying
+/// until `timeout` passes. The store itself can't be locked because saving
+/// may replace it with a new file.
+fn lock_store(path: &Path, timeout: Duration) -> Result<std::fs::File> {
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(with_suffix(path, ".lock"))?;
+    let start = Instant::now();
+    loop {
+        match file.try_lock() {
+            Ok(()) => return Ok(file),
+            Err(TryLockError::WouldBlock) if start.elapsed() < timeout => {
+                std::thread::sleep(LOCK_RETRY);
+            }
+            Err(TryLockError::WouldBlock) => {
+                return Err(LockError::Timeout(path.to_path_buf(), timeout).into())
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    use super::*;
+
+    /// The time tests upgrade and save stores at.
+    pub(super) fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 1, 9, 0, 0).unwrap()
+    }
+
+    /// A task as the handler would save it.
+    pub(super) fn task(name: &str) -> TaskProperties {
+        serde_json::from_value(json!({
+            "uuid": Uuid::new_v4(),
+            "name": name,
+            "desc": "",
+            "status": "active",
+            "created_at": "2024-03-01T09:00:00Z",
+            "modified_at": "2024-03-01T09:00:00Z",
+        }))
+        .unwrap()
+    }
+
+    /// Saves a store with `count` tasks in it, opening it anew each time.
+    fn save_tasks(path: &Path, count: u32) {
+        let mut store = open(path, Duration::ZERO, now()).unwrap();
+        let mut meta = store.load().unwrap();
+        for id in meta.next_id..meta.next_id + count {
+            store.insert(id, &task(&format!("task {}", id))).unwrap();
+        }
+        meta.next_id += count;
+        store.save(&meta).unwrap();
+    }
+
+    #[test]
+    fn upgrades_unversioned_save() {
+        let mut value = json!({
+            "tasks": {
+                "write": {"desc": "report", "status": "f"},
+                "read": {"desc": "", "status": "bogus"},
+            }
+        });
+        assert_eq!(upgrade(&mut value, now()).unwrap(), 0);
+        assert_eq!(value["version"], SAVE_VERSION);
+
+        let snapshot: Snapshot = serde_json::from_value(value).unwrap();
+        assert_eq!(snapshot.next_id, 3);
+        // Short IDs are handed out in name order.
+        let read = &snapshot.tasks[&1];
+        let write = &snapshot.tasks[&2];
+        assert_eq!(read.name, "read");
+        assert_eq!(read.status, Status::Active);
+        assert_eq!(write.name, "write");
+        assert_eq!(write.desc, "report");
+        assert_eq!(write.status, Status::Completed);
+        assert_ne!(read.uuid, write.uuid);
+    }
+
+    #[test]
+    fn upgrades_v1_save() {
+        let mut value = json!({
+            "version": 1,
+            "tasks": {"plan": {"desc": "", "status": "stopped"}},
+        });
+        assert_eq!(upgrade(&mut value, now()).unwrap(), 1);
+
+        let snapshot: Snapshot = serde_json::from_value(value).unwrap();
+        let task = &snapshot.tasks[&1];
+        assert_eq!(task.name, "plan");
+        assert_eq!(task.status, Status::Stopped);
+        assert_eq!(task.times.created_at, now());
+        assert_eq!(task.times.modified_at, now());
+    }
+
+    #[test]
+    fn keeps_current_save() {
+        let mut value = serde_json::to_value(Snapshot::default()).unwrap();
+        let before = value.clone();
+        assert_eq!(upgrade(&mut value, now()).unwrap(), SAVE_VERSION);
+        assert_eq!(value, before);
+    }
+
+    #[test]
+    fn rejects_newer_save() {
+        let mut value = json!({"version": SAVE_VERSION + 1, "next_id": 1, "tasks": {}});
+        assert!(upgrade(&mut value, now()).is_err());
+    }
+
+    /// Makes the newest backup of the store at `path` a day old.
+    pub(super) fn age_backup(path: &Path) {
+        if let Ok(backup) = std::fs::File::options()
+            .write(true)
+            .open(backup_path(path, 1))
+        {
+            let day = Duration::from_secs(24 * 60 * 60);
+            backup
+                .set_modified(std::time::SystemTime::now() - day)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn saves_replace_the_file_whole() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SAVE_FILE_NAME);
+        save_tasks(&path, 2);
+
+        let names: Vec<String> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        assert!(!names.iter().any(|name| name.ends_with(".tmp")));
+        assert_eq!(read(&path, now()).unwrap().tasks.len(), 2);
+    }
+
+    #[test]
+    fn rotates_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [SAVE_FILE_NAME, DB_FILE_NAME] {
+            let path = dir.path().join(name);
+            for _ in 0..BACKUP_COUNT + 2 {
+                save_tasks(&path, 1);
+                // SQLite stores only back up once the newest backup has aged.
+                age_backup(&path);
+            }
+
+            let store = open(&path, Duration::ZERO, now()).unwrap();
+            let backups = store.backups();
+            let numbers: Vec<usize> = backups.iter().map(|b| b.number).collect();
+            assert_eq!(numbers, (1..=BACKUP_COUNT).collect::<Vec<_>>());
+            // Newest first, each save added a task.
+            let tasks: Vec<Option<usize>> = backups.iter().map(|b| b.tasks).collect();
+            let expected = (2..=BACKUP_COUNT + 1).rev().map(Some).collect::<Vec<_>>();
+            assert_eq!(tasks, expected, "{}", name);
+            assert_eq!(store.read_backup(1).unwrap().tasks.len(), BACKUP_COUNT + 1);
+            assert!(store.read_backup(BACKUP_COUNT + 1).is_err());
+        }
+    }
+
+    #[test]
+    fn locks_the_store_until_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [SAVE_FILE_NAME, DB_FILE_NAME] {
+            let path = dir.path().join(name);
+            let store = open(&path, Duration::ZERO, now()).unwrap();
+            let timeout = Duration::from_millis(50);
+            let start = Instant::now();
+            let locked = open(&path, timeout, now()).map(|_| ());
+            assert!(start.elapsed() >= timeout);
+            assert!(locked.is_err_and(|err| matches!(
+                err.kind(),
+                ErrorType::Lock(LockError::Timeout(locked, waited))
+                    if *locked == path && *waited == timeout
+            )));
+            assert!(lock_store(&path, Duration::ZERO).is_err());
+
+            drop(store);
+            let lock = lock_store(&path, Duration::ZERO).unwrap();
+            drop(lock);
+            assert!(open(&path, Duration::ZERO, now()).is_ok());
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::{
+    backup_path, create_dir, list_backups, lock_store, rotate_backups, upgrade, with_suffix,
+    Backup, MemoryStore, Query, Snapshot, StoreMeta, TaskStore, SAVE_VERSION,
+};
+use crate::{
+    handler::{HistoryEntry, TaskProperties},
+    result::*,
+};
+
+/// Keeps every task in one JSON file, read whole when opening the store
+/// and written whole when saving it. In between the tasks are kept in a
+/// [`MemoryStore`].
+pub struct JsonStore {
+    path: PathBuf,
+    tasks: MemoryStore,
+    /// Held for the store's whole life so concurrent runs don't overwrite
+    /// each other's changes.
+    _lock: File,
+    /// The file was created by this run, there is nothing to back up yet.
+    created: bool,
+    /// When the store was opened, for upgrading backups.
+    opened_at: DateTime<Utc>,
+}
+
+impl JsonStore {
+    /// Loads the tasks saved in the file at `path`, creating it and its
+    /// directory if needed. Saves upgraded from before tasks had timestamps
+    /// get `now`.
+    pub fn open(path: &Path, lock_timeout: Duration, now: DateTime<Utc>) -> Result<Self> {
+        create_dir(path)?;
+        let lock = lock_store(path, lock_timeout)?;
+        let created = !path.is_file();
+        let data = if created {
+            let data = Snapshot::default();
+            write_atomic(path, &serde_json::to_string(&data)?)?;
+            data
+        } else {
+            load_save(path, now)?
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            tasks: MemoryStore::from(data),
+            _lock: lock,
+            created,
+            opened_at: now,
+        })
+    }
+}
+
+impl TaskStore for JsonStore {
+    fn load(&mut self) -> Result<StoreMeta> {
+        self.tasks.load()
+    }
+
+    fn get(&self, id: u32) -> Result<Option<TaskProperties>> {
+        self.tasks.get(id)
+    }
+
+    fn insert(&mut self, id: u32, task: &TaskProperties) -> Result<()> {
+        self.tasks.insert(id, task)
+    }
+
+    fn update(&mut self, id: u32, task: &TaskProperties) -> Result<()> {
+        self.tasks.update(id, task)
+    }
+
+    fn delete(&mut self, id: u32) -> Result<()> {
+        self.tasks.delete(id)
+    }
+
+    fn query(&self, query: &Query) -> Result<Vec<(u32, TaskProperties)>> {
+        self.tasks.query(query)
+    }
+
+    fn history(&self, uuid: Uuid) -> Result<Vec<HistoryEntry>> {
+        self.tasks.history(uuid)
+    }
+
+    fn add_history(&mut self, uuid: Uuid, entries: &[HistoryEntry]) -> Result<()> {
+        self.tasks.add_history(uuid, entries)
+    }
+
+    /// Writes the file if anything changed, rotating the previous save
+    /// into the backups first.
+    fn save(&mut self, meta: &StoreMeta) -> Result<()> {
+        self.tasks.save(meta)?;
+        let serialized = serde_json::to_string_pretty(self.tasks.data())?;
+        if std::fs::read_to_string(&self.path).is_ok_and(|saved| saved == serialized) {
+            return Ok(());
+        }
+        if !self.created {
+            rotate_backups(&self.path)?;
+        }
+        write_atomic(&self.path, &serialized)?;
+        self.created = false;
+        Ok(())
+    }
+
+    fn snapshot(&self) -> Result<Snapshot> {
+        self.tasks.snapshot()
+    }
+
+    fn replace(&mut self, snapshot: Snapshot) -> Result<()> {
+        self.tasks.replace(snapshot)
+    }
+
+    fn backups(&self) -> Vec<Backup> {
+        list_backups(&self.path, |path| {
+            std::fs::read_to_string(path)
+                .ok()
+                .and_then(|raw| serde_json::from_str::<Value>(&raw).ok())
+                .and_then(|value| value.get("tasks")?.as_object().map(|t| t.len()))
+        })
+    }
+
+    fn read_backup(&self, number: usize) -> Result<Snapshot> {
+        let path = backup_path(&self.path, number);
+        if !path.is_file() {
+            return Err(SystemError::BackupDoesntExist(number).into());
+        }
+        read(&path, self.opened_at)
+    }
+}
+
+/// Reads the save file, upgrading it in memory only.
+pub(super) fn read(path: &Path, now: DateTime<Utc>) -> Result<Snapshot> {
+    Ok(parse_save(&std::fs::read_to_string(path)?, now)?.0)
+}
+
+/// Reads the save file, upgrading it to [`SAVE_VERSION`] if it was written
+/// by an older version. The original file is backed up before migrating.
+fn load_save(save_path: &Path, now: DateTime<Utc>) -> Result<Snapshot> {
+    let raw = std::fs::read_to_string(save_path)?;
+    let (data, version) = parse_save(&raw, now)?;
+    if version < SAVE_VERSION {
+        std::fs::write(with_suffix(save_path, &format!(".v{}.bak", version)), &raw)?;
+    }
+    Ok(data)
+}
+
+/// Parses a save, upgrading it in memory only, along with the version it
+/// was written in.
+fn parse_save(raw: &str, now: DateTime<Utc>) -> Result<(Snapshot, u32)> {
+    let mut value: Value = serde_json::from_str(raw)?;
+    let version = upgrade(&mut value, now)?;
+    Ok((serde_json::from_value(value)?, version))
+}
+
+/// Writes to a temporary file next to `path` and renames it over `path`,
+/// so a crash mid-write leaves either the old or the new contents behind.
+fn write_atomic(path: &Path, contents: &str) -> Result<()> {
+    let temp = with_suffix(path, ".tmp");
+    let mut file = File::create(&temp)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    drop(file);
+    std::fs::rename(&temp, path)?;
+
+    // Persist the rename itself, not every platform can open directories.
+    if let Some(dir) = path.parent().and_then(|dir| File::open(dir).ok()) {
+        dir.sync_all().ok();
+    }
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use uuid::Uuid;
+
+use super::{Backup, Query, Snapshot, StoreMeta, TaskStore};
+use crate::{
+    handler::{HistoryEntry, TaskProperties},
+    result::*,
+};
+
+/// Keeps the tasks in memory only, nothing outlives the store. Lets the
+/// handler run without a file, for dry runs and for tools embedding it.
+/// `MemoryStore::default()` starts out empty.
+#[derive(Default)]
+pub struct MemoryStore {
+    data: Snapshot,
+}
+
+impl MemoryStore {
+    /// Everything in the store, without copying it like `snapshot` does.
+    pub(super) fn data(&self) -> &Snapshot {
+        &self.data
+    }
+}
+
+impl From<Snapshot> for MemoryStore {
+    fn from(data: Snapshot) -> Self {
+        Self { data }
+    }
+}
+
+impl TaskStore for MemoryStore {
+    fn load(&mut self) -> Result<StoreMeta> {
+        Ok(self.data.meta())
+    }
+
+    fn get(&self, id: u32) -> Result<Option<TaskProperties>> {
+        Ok(self.data.tasks.get(&id).cloned())
+    }
+
+    fn insert(&mut self, id: u32, task: &TaskProperties) -> Result<()> {
+        self.data.tasks.insert(id, task.clone());
+        Ok(())
+    }
+
+    fn update(&mut self, id: u32, task: &TaskProperties) -> Result<()> {
+        self.insert(id, task)
+    }
+
+    fn delete(&mut self, id: u32) -> Result<()> {
+        self.data.tasks.remove(&id);
+        Ok(())
+    }
+
+    fn query(&self, query: &Query) -> Result<Vec<(u32, TaskProperties)>> {
+        Ok(self
+            .data
+            .tasks
+            .iter()
+            .filter(|(_, task)| query.matches(task))
+            .take(query.limit.unwrap_or(usize::MAX))
+            .map(|(id, task)| (*id, task.clone()))
+            .collect())
+    }
+
+    fn history(&self, uuid: Uuid) -> Result<Vec<HistoryEntry>> {
+        Ok(self.data.history.get(&uuid).cloned().unwrap_or_default())
+    }
+
+    fn add_history(&mut self, uuid: Uuid, entries: &[HistoryEntry]) -> Result<()> {
+        self.data
+            .history
+            .entry(uuid)
+            .or_default()
+            .extend_from_slice(entries);
+        Ok(())
+    }
+
+    fn save(&mut self, meta: &StoreMeta) -> Result<()> {
+        self.data.next_id = meta.next_id;
+        self.data.journal = meta.journal.clone();
+        Ok(())
+    }
+
+    fn snapshot(&self) -> Result<Snapshot> {
+        Ok(self.data.clone())
+    }
+
+    fn replace(&mut self, snapshot: Snapshot) -> Result<()> {
+        self.data = snapshot;
+        Ok(())
+    }
+
+    /// Nothing is written, so there are no backups.
+    fn backups(&self) -> Vec<Backup> {
+        Vec::new()
+    }
+
+    fn read_backup(&self, number: usize) -> Result<Snapshot> {
+        Err(SystemError::BackupDoesntExist(number).into())
+    }
+}
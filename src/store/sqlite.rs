@@ -0,0 +1,757 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use rusqlite::{
+    params, params_from_iter, types::Value as SqlValue, Connection, OpenFlags, OptionalExtension,
+};
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::{
+    backup_path, create_dir, list_backups, lock_store, rotate_backups, upgrade, with_suffix,
+    Backup, Query, Snapshot, StoreMeta, TaskStore, SAVE_VERSION,
+};
+use crate::{
+    handler::{HistoryEntry, TaskProperties},
+    result::*,
+    task::Status,
+};
+
+/// Tasks are saved whole in `data`, the other columns copy the fields
+/// queries select on. The journal has a row per undoable or redoable run,
+/// each stack ordered by `seq`.
+const SCHEMA: &str = "
+    CREATE TABLE tasks (
+        id INTEGER PRIMARY KEY,
+        uuid TEXT NOT NULL UNIQUE,
+        name TEXT NOT NULL,
+        project TEXT,
+        parent INTEGER,
+        status TEXT NOT NULL,
+        due TEXT,
+        archived INTEGER NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX tasks_name ON tasks (name);
+    CREATE INDEX tasks_parent ON tasks (parent);
+    CREATE INDEX tasks_status ON tasks (status);
+    CREATE INDEX tasks_due ON tasks (due);
+    CREATE TABLE tags (
+        task INTEGER NOT NULL,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (tag, task)
+    ) WITHOUT ROWID;
+    CREATE INDEX tags_task ON tags (task);
+    CREATE TABLE depends (
+        task INTEGER NOT NULL,
+        dependency INTEGER NOT NULL,
+        PRIMARY KEY (dependency, task)
+    ) WITHOUT ROWID;
+    CREATE INDEX depends_task ON depends (task);
+    CREATE TABLE history (
+        seq INTEGER PRIMARY KEY,
+        uuid TEXT NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX history_uuid ON history (uuid);
+    CREATE TABLE meta (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    CREATE TABLE journal (
+        seq INTEGER PRIMARY KEY,
+        stack TEXT NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX journal_stack ON journal (stack, seq);
+";
+
+/// The journal's stacks, as named in its JSON layout.
+const STACKS: [&str; 2] = ["undo", "redo"];
+
+/// How old the newest backup gets before a write takes another one.
+const BACKUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Keeps tasks in an SQLite database and only reads the ones a run asks
+/// for, status, due date and tags are indexed for queries. Only the
+/// changed tasks and journal entries are written. Backups copy the whole
+/// file, so they are taken at most once every [`BACKUP_INTERVAL`] and
+/// before replacing everything.
+pub struct SqliteStore {
+    path: PathBuf,
+    conn: Connection,
+    /// Held for the store's whole life so concurrent runs don't overwrite
+    /// each other's changes.
+    _lock: File,
+    /// The database was created by this run, there is nothing to back up yet.
+    created: bool,
+    /// A transaction holding the changes since the last save is open.
+    writing: bool,
+    /// The meta data as last read or written, so unchanged meta data
+    /// isn't written again.
+    meta: Option<StoreMeta>,
+    /// When the store was opened, for upgrading backups.
+    opened_at: DateTime<Utc>,
+}
+
+impl SqliteStore {
+    /// Opens the database at `path`, creating it and its directory if
+    /// needed. Databases upgraded from before tasks had timestamps get
+    /// `now`.
+    pub fn open(path: &Path, lock_timeout: Duration, now: DateTime<Utc>) -> Result<Self> {
+        create_dir(path)?;
+        let lock = lock_store(path, lock_timeout)?;
+        let created = !path.is_file();
+        let conn = Connection::open(path)?;
+        let mut store = Self {
+            path: path.to_path_buf(),
+            conn,
+            _lock: lock,
+            created,
+            writing: false,
+            meta: None,
+            opened_at: now,
+        };
+        if created {
+            store.begin()?;
+            store.conn.execute_batch(SCHEMA)?;
+            store.replace(Snapshot::default())?;
+            store.commit()?;
+        } else {
+            store.upgrade()?;
+        }
+        Ok(store)
+    }
+
+    /// Brings databases written by older versions up to [`SAVE_VERSION`],
+    /// going through the layout of the JSON save. The original database is
+    /// backed up before migrating.
+    fn upgrade(&mut self) -> Result<()> {
+        let version = version(&self.conn)?;
+        if version == SAVE_VERSION {
+            return Ok(());
+        }
+        let mut value = read_save(&self.conn)?;
+        upgrade(&mut value, self.opened_at)?;
+        std::fs::copy(
+            &self.path,
+            with_suffix(&self.path, &format!(".v{}.bak", version)),
+        )?;
+        self.begin()?;
+        self.replace(serde_json::from_value(value)?)?;
+        self.commit()
+    }
+
+    /// Opens a transaction for the changes until the next save, backing
+    /// up the database first if the newest backup is older than
+    /// [`BACKUP_INTERVAL`] or `backup` is set.
+    fn begin_with(&mut self, backup: bool) -> Result<()> {
+        if !self.writing {
+            if !self.created && (backup || backup_due(&self.path)) {
+                rotate_backups(&self.path)?;
+            }
+            self.conn.execute_batch("BEGIN IMMEDIATE")?;
+            self.writing = true;
+        }
+        Ok(())
+    }
+
+    /// See [`Self::begin_with`], backing up only when one is due.
+    fn begin(&mut self) -> Result<()> {
+        self.begin_with(false)
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        if self.writing {
+            self.conn.execute_batch("COMMIT")?;
+            self.writing = false;
+        }
+        Ok(())
+    }
+
+    fn write_task(&self, id: u32, task: &TaskProperties) -> Result<()> {
+        self.conn
+            .prepare_cached(
+                "INSERT INTO tasks (id, uuid, name, project, parent, status, due, archived, data)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            )?
+            .execute(params![
+                id,
+                task.uuid.to_string(),
+                task.name,
+                task.project,
+                task.parent,
+                status_column(task.status)?,
+                task.due.map(|due| due.to_string()),
+                task.archived,
+                serde_json::to_string(task)?,
+            ])?;
+        let mut tag = self
+            .conn
+            .prepare_cached("INSERT INTO tags (task, tag) VALUES (?1, ?2)")?;
+        for name in &task.tags {
+            tag.execute(params![id, name])?;
+        }
+        let mut depends = self
+            .conn
+            .prepare_cached("INSERT INTO depends (task, dependency) VALUES (?1, ?2)")?;
+        for dependency in &task.depends {
+            depends.execute(params![id, dependency])?;
+        }
+        Ok(())
+    }
+
+    fn remove_task(&self, id: u32) -> Result<()> {
+        for sql in [
+            "DELETE FROM tasks WHERE id = ?1",
+            "DELETE FROM tags WHERE task = ?1",
+            "DELETE FROM depends WHERE task = ?1",
+        ] {
+            self.conn.prepare_cached(sql)?.execute([id])?;
+        }
+        Ok(())
+    }
+
+    fn write_history(&self, uuid: Uuid, entries: &[HistoryEntry]) -> Result<()> {
+        let mut insert = self
+            .conn
+            .prepare_cached("INSERT INTO history (uuid, data) VALUES (?1, ?2)")?;
+        for entry in entries {
+            insert.execute(params![uuid.to_string(), serde_json::to_string(entry)?])?;
+        }
+        Ok(())
+    }
+
+    /// Meta data is saved as JSON values under the name of its field, except
+    /// for the journal which gets a row per transaction.
+    fn write_meta(&mut self, meta: &StoreMeta) -> Result<()> {
+        self.conn
+            .prepare_cached("INSERT OR REPLACE INTO meta (key, value) VALUES (?1, ?2)")?
+            .execute(params!["next_id", serde_json::to_string(&meta.next_id)?])?;
+        let old = match &self.meta {
+            Some(old) => serde_json::to_value(&old.journal)?,
+            None => Value::Null,
+        };
+        let new = serde_json::to_value(&meta.journal)?;
+        for stack in STACKS {
+            self.write_stack(
+                stack,
+                stack_entries(&old, stack),
+                stack_entries(&new, stack),
+            )?;
+        }
+        self.meta = Some(meta.clone());
+        Ok(())
+    }
+
+    /// Undo and redo push and pop the newest entries of a stack, and a full
+    /// stack drops its oldest ones, so `new` is a run of `old` followed by
+    /// pushed entries. Keeps the longest such run and only deletes and
+    /// inserts the rows around it.
+    fn write_stack(&self, stack: &str, old: &[Value], new: &[Value]) -> Result<()> {
+        let (dropped, kept) = (0..=old.len())
+            .map(|dropped| {
+                let kept = old[dropped..]
+                    .iter()
+                    .zip(new)
+                    .take_while(|(old, new)| old == new)
+                    .count();
+                (dropped, kept)
+            })
+            .max_by_key(|&(dropped, kept)| (kept, std::cmp::Reverse(dropped)))
+            .unwrap();
+        let popped = old.len() - dropped - kept;
+        for (order, count) in [("ASC", dropped), ("DESC", popped)] {
+            self.conn
+                .prepare_cached(&format!(
+                    "DELETE FROM journal WHERE seq IN
+                     (SELECT seq FROM journal WHERE stack = ?1 ORDER BY seq {} LIMIT ?2)",
+                    order
+                ))?
+                .execute(params![stack, count as i64])?;
+        }
+        let mut insert = self
+            .conn
+            .prepare_cached("INSERT INTO journal (stack, data) VALUES (?1, ?2)")?;
+        for entry in &new[kept..] {
+            insert.execute(params![stack, serde_json::to_string(entry)?])?;
+        }
+        Ok(())
+    }
+
+    fn read_meta(&self, key: &str) -> Result<Option<Value>> {
+        let value: Option<String> = self
+            .conn
+            .prepare_cached("SELECT value FROM meta WHERE key = ?1")?
+            .query_row([key], |row| row.get(0))
+            .optional()?;
+        Ok(value.map(|v| serde_json::from_str(&v)).transpose()?)
+    }
+}
+
+impl TaskStore for SqliteStore {
+    fn load(&mut self) -> Result<StoreMeta> {
+        let meta = StoreMeta {
+            next_id: self
+                .read_meta("next_id")?
+                .map(serde_json::from_value)
+                .transpose()?
+                .unwrap_or(1),
+            journal: serde_json::from_value(read_journal(&self.conn)?)?,
+        };
+        self.meta = Some(meta.clone());
+        Ok(meta)
+    }
+
+    fn get(&self, id: u32) -> Result<Option<TaskProperties>> {
+        let data: Option<String> = self
+            .conn
+            .prepare_cached("SELECT data FROM tasks WHERE id = ?1")?
+            .query_row([id], |row| row.get(0))
+            .optional()?;
+        Ok(data.map(|data| serde_json::from_str(&data)).transpose()?)
+    }
+
+    fn insert(&mut self, id: u32, task: &TaskProperties) -> Result<()> {
+        self.begin()?;
+        self.write_task(id, task)
+    }
+
+    fn update(&mut self, id: u32, task: &TaskProperties) -> Result<()> {
+        self.begin()?;
+        self.remove_task(id)?;
+        self.write_task(id, task)
+    }
+
+    fn delete(&mut self, id: u32) -> Result<()> {
+        self.begin()?;
+        self.remove_task(id)
+    }
+
+    fn query(&self, query: &Query) -> Result<Vec<(u32, TaskProperties)>> {
+        let mut conditions: Vec<String> = Vec::new();
+        let mut values: Vec<SqlValue> = Vec::new();
+        if let Some(name) = &query.name {
+            conditions.push(String::from("name = ?"));
+            values.push(SqlValue::Text(name.clone()));
+        }
+        match &query.project {
+            Some(Some(project)) => {
+                conditions.push(String::from("project = ?"));
+                values.push(SqlValue::Text(project.clone()));
+            }
+            Some(None) => conditions.push(String::from("project IS NULL")),
+            None => (),
+        }
+        if let Some(uuid) = query.uuid {
+            conditions.push(String::from("uuid = ?"));
+            values.push(SqlValue::Text(uuid.to_string()));
+        }
+        if let Some(parent) = query.parent {
+            conditions.push(String::from("parent = ?"));
+            values.push(SqlValue::Integer(parent.into()));
+        }
+        if let Some(id) = query.depends_on {
+            conditions.push(String::from(
+                "id IN (SELECT task FROM depends WHERE dependency = ?)",
+            ));
+            values.push(SqlValue::Integer(id.into()));
+        }
+        if !query.statuses.is_empty() {
+            let marks = vec!["?"; query.statuses.len()].join(", ");
+            conditions.push(format!("status IN ({})", marks));
+            for status in &query.statuses {
+                values.push(SqlValue::Text(status_column(*status)?));
+            }
+        }
+        for tag in &query.tags {
+            conditions.push(String::from("id IN (SELECT task FROM tags WHERE tag = ?)"));
+            values.push(SqlValue::Text(tag.clone()));
+        }
+        if let Some(date) = query.due_before {
+            conditions.push(String::from("due < ?"));
+            values.push(SqlValue::Text(date.to_string()));
+        }
+        if let Some(date) = query.due_after {
+            conditions.push(String::from("due > ?"));
+            values.push(SqlValue::Text(date.to_string()));
+        }
+        if let Some(archived) = query.archived {
+            conditions.push(String::from("archived = ?"));
+            values.push(SqlValue::Integer(archived.into()));
+        }
+
+        let mut sql = String::from("SELECT id, data FROM tasks");
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        sql.push_str(" ORDER BY id");
+        if let Some(limit) = query.limit {
+            sql.push_str(&format!(" LIMIT {}", limit));
+        }
+
+        let mut statement = self.conn.prepare_cached(&sql)?;
+        let mut rows = statement.query(params_from_iter(values))?;
+        let mut tasks = Vec::new();
+        while let Some(row) = rows.next()? {
+            let data: String = row.get(1)?;
+            tasks.push((row.get(0)?, serde_json::from_str(&data)?));
+        }
+        Ok(tasks)
+    }
+
+    fn history(&self, uuid: Uuid) -> Result<Vec<HistoryEntry>> {
+        let mut statement = self
+            .conn
+            .prepare_cached("SELECT data FROM history WHERE uuid = ?1 ORDER BY seq")?;
+        let mut rows = statement.query([uuid.to_string()])?;
+        let mut entries = Vec::new();
+        while let Some(row) = rows.next()? {
+            let data: String = row.get(0)?;
+            entries.push(serde_json::from_str(&data)?);
+        }
+        Ok(entries)
+    }
+
+    fn add_history(&mut self, uuid: Uuid, entries: &[HistoryEntry]) -> Result<()> {
+        self.begin()?;
+        self.write_history(uuid, entries)
+    }
+
+    /// Commits the changes since the last save, if there were any.
+    fn save(&mut self, meta: &StoreMeta) -> Result<()> {
+        if self.meta.as_ref() != Some(meta) {
+            self.begin()?;
+            self.write_meta(meta)?;
+        }
+        self.commit()?;
+        self.created = false;
+        Ok(())
+    }
+
+    fn snapshot(&self) -> Result<Snapshot> {
+        Ok(serde_json::from_value(read_save(&self.conn)?)?)
+    }
+
+    /// Backs up the database first, so a restore can be rolled back.
+    fn replace(&mut self, snapshot: Snapshot) -> Result<()> {
+        self.begin_with(true)?;
+        self.conn.execute_batch(
+            "DELETE FROM tasks; DELETE FROM tags; DELETE FROM depends;
+             DELETE FROM history; DELETE FROM meta; DELETE FROM journal;",
+        )?;
+        self.meta = None;
+        for (id, task) in &snapshot.tasks {
+            self.write_task(*id, task)?;
+        }
+        for (uuid, entries) in &snapshot.history {
+            self.write_history(*uuid, entries)?;
+        }
+        self.write_meta(&snapshot.meta())?;
+        self.conn
+            .pragma_update(None, "user_version", SAVE_VERSION)?;
+        Ok(())
+    }
+
+    fn backups(&self) -> Vec<Backup> {
+        list_backups(&self.path, |path| {
+            Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+                .ok()?
+                .query_row("SELECT count(*) FROM tasks", [], |row| row.get::<_, i64>(0))
+                .ok()
+                .map(|count| count as usize)
+        })
+    }
+
+    fn read_backup(&self, number: usize) -> Result<Snapshot> {
+        let path = backup_path(&self.path, number);
+        if !path.is_file() {
+            return Err(SystemError::BackupDoesntExist(number).into());
+        }
+        read(&path, self.opened_at)
+    }
+}
+
+/// Reads the database without writing to it, upgrading it in memory only.
+pub(super) fn read(path: &Path, now: DateTime<Utc>) -> Result<Snapshot> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut value = read_save(&conn)?;
+    upgrade(&mut value, now)?;
+    Ok(serde_json::from_value(value)?)
+}
+
+/// The save format version the database was written with.
+fn version(conn: &Connection) -> Result<u32> {
+    Ok(conn.pragma_query_value(None, "user_version", |row| row.get(0))?)
+}
+
+/// Whether the newest backup of the database at `path` is missing or older
+/// than [`BACKUP_INTERVAL`].
+fn backup_due(path: &Path) -> bool {
+    std::fs::metadata(backup_path(path, 1))
+        .and_then(|backup| backup.modified())
+        .ok()
+        .and_then(|saved_at| saved_at.elapsed().ok())
+        .is_none_or(|age| age >= BACKUP_INTERVAL)
+}
+
+/// Reads the journal rows in its JSON layout.
+fn read_journal(conn: &Connection) -> Result<Value> {
+    let mut journal = serde_json::Map::new();
+    for stack in STACKS {
+        journal.insert(stack.to_owned(), Value::Array(Vec::new()));
+    }
+    let mut statement = conn.prepare_cached("SELECT stack, data FROM journal ORDER BY seq")?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        let data: String = row.get(1)?;
+        if let Some(Value::Array(entries)) = journal.get_mut(&row.get::<_, String>(0)?) {
+            entries.push(serde_json::from_str(&data)?);
+        }
+    }
+    Ok(Value::Object(journal))
+}
+
+fn stack_entries<'a>(journal: &'a Value, stack: &str) -> &'a [Value] {
+    journal
+        .get(stack)
+        .and_then(Value::as_array)
+        .map_or(&[], Vec::as_slice)
+}
+
+/// How statuses are written in the `status` column, the same as in JSON.
+fn status_column(status: Status) -> Result<String> {
+    match serde_json::to_value(status)? {
+        Value::String(status) => Ok(status),
+        _ => unreachable!("Unreachable!"),
+    }
+}
+
+/// Reads the whole database in the layout of the JSON save.
+fn read_save(conn: &Connection) -> Result<Value> {
+    let mut save = serde_json::Map::new();
+    save.insert(String::from("version"), Value::from(version(conn)?));
+
+    let mut statement = conn.prepare("SELECT key, value FROM meta")?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        let value: String = row.get(1)?;
+        save.insert(row.get(0)?, serde_json::from_str(&value)?);
+    }
+    save.insert(String::from("journal"), read_journal(conn)?);
+
+    let mut tasks = serde_json::Map::new();
+    let mut statement = conn.prepare("SELECT id, data FROM tasks ORDER BY id")?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        let id: u32 = row.get(0)?;
+        let data: String = row.get(1)?;
+        tasks.insert(id.to_string(), serde_json::from_str(&data)?);
+    }
+    save.insert(String::from("tasks"), Value::Object(tasks));
+
+    let mut history = serde_json::Map::new();
+    let mut statement = conn.prepare("SELECT uuid, data FROM history ORDER BY seq")?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        let data: String = row.get(1)?;
+        let entries = history
+            .entry(row.get::<_, String>(0)?)
+            .or_insert_with(|| Value::Array(Vec::new()));
+        if let Value::Array(entries) = entries {
+            entries.push(serde_json::from_str(&data)?);
+        }
+    }
+    save.insert(String::from("history"), Value::Object(history));
+    Ok(Value::Object(save))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::{
+        clock::SystemClock,
+        handler::TaskHandler,
+        store::{tests::age_backup, tests::now, tests::task, MemoryStore, DB_FILE_NAME},
+    };
+
+    fn open(dir: &tempfile::TempDir) -> SqliteStore {
+        SqliteStore::open(&dir.path().join(DB_FILE_NAME), Duration::ZERO, now()).unwrap()
+    }
+
+    /// Sequence numbers of a journal stack's rows, oldest first.
+    fn journal_rows(store: &SqliteStore, stack: &str) -> Vec<i64> {
+        let mut statement = store
+            .conn
+            .prepare("SELECT seq FROM journal WHERE stack = ?1 ORDER BY seq")
+            .unwrap();
+        let rows = statement.query_map([stack], |row| row.get(0)).unwrap();
+        rows.map(|seq| seq.unwrap()).collect()
+    }
+
+    #[test]
+    fn round_trips_tasks() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = open(&dir);
+        let mut meta = store.load().unwrap();
+        let mut write = task("write");
+        write.tags.insert(String::from("work"));
+        write.depends.insert(2);
+        store.insert(1, &write).unwrap();
+        store.insert(2, &task("read")).unwrap();
+        assert_eq!(store.get(1).unwrap(), Some(write.clone()));
+
+        write.name = String::from("draft");
+        write.tags.clear();
+        store.update(1, &write).unwrap();
+        store.delete(2).unwrap();
+        meta.next_id = 3;
+        store.save(&meta).unwrap();
+        drop(store);
+
+        let mut store = open(&dir);
+        assert_eq!(store.load().unwrap(), meta);
+        assert_eq!(store.get(1).unwrap(), Some(write));
+        assert_eq!(store.get(2).unwrap(), None);
+        let tagged = Query {
+            tags: vec![String::from("work")],
+            ..Default::default()
+        };
+        assert!(store.query(&tagged).unwrap().is_empty());
+    }
+
+    #[test]
+    fn queries_select_like_the_memory_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = open(&dir);
+        let mut memory = MemoryStore::default();
+        let due = NaiveDate::from_ymd_opt(2024, 3, 6).unwrap();
+        for id in 1..=12 {
+            let mut task = task(&format!("task {}", id % 4));
+            task.status = [Status::Active, Status::Stopped, Status::Completed][id as usize % 3];
+            task.project = (id % 2 == 0).then(|| String::from("work"));
+            task.parent = (id > 6).then(|| id - 6);
+            task.due = (id % 3 != 0).then(|| due + chrono::Days::new(id.into()));
+            task.archived = id == 12;
+            if id % 4 == 0 {
+                task.tags.insert(String::from("urgent"));
+            }
+            if id % 5 == 0 {
+                task.depends.insert(1);
+            }
+            store.insert(id, &task).unwrap();
+            memory.insert(id, &task).unwrap();
+        }
+
+        let queries = [
+            Query::default(),
+            Query {
+                name: Some(String::from("task 1")),
+                ..Default::default()
+            },
+            Query {
+                project: Some(None),
+                statuses: vec![Status::Active, Status::Completed],
+                ..Default::default()
+            },
+            Query {
+                project: Some(Some(String::from("work"))),
+                tags: vec![String::from("urgent")],
+                ..Default::default()
+            },
+            Query {
+                parent: Some(2),
+                ..Default::default()
+            },
+            Query {
+                depends_on: Some(1),
+                ..Default::default()
+            },
+            Query {
+                due_after: Some(due + chrono::Days::new(2)),
+                due_before: Some(due + chrono::Days::new(10)),
+                archived: Some(false),
+                limit: Some(3),
+                ..Default::default()
+            },
+        ];
+        for query in queries {
+            let ids = |tasks: Vec<(u32, TaskProperties)>| -> Vec<u32> {
+                tasks.into_iter().map(|(id, _)| id).collect()
+            };
+            assert_eq!(
+                ids(store.query(&query).unwrap()),
+                ids(memory.query(&query).unwrap()),
+                "{:?}",
+                query
+            );
+        }
+    }
+
+    #[test]
+    fn undoes_through_the_journal() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(DB_FILE_NAME);
+        let open_handler = || {
+            let store = SqliteStore::open(&path, Duration::ZERO, now()).unwrap();
+            TaskHandler::new(Box::new(store), Box::new(SystemClock)).unwrap()
+        };
+        for name in ["write", "read", "plan"] {
+            let mut handler = open_handler();
+            handler.create_task(name, None).unwrap();
+            handler.commit(name).unwrap();
+            handler.save().unwrap();
+        }
+        let before = journal_rows(&open(&dir), "undo");
+        assert_eq!(before.len(), 3);
+
+        let mut handler = open_handler();
+        handler.undo().unwrap();
+        handler.save().unwrap();
+        drop(handler);
+        let store = open(&dir);
+        // Only the undone run's row moved, the others were left alone.
+        assert_eq!(journal_rows(&store, "undo"), before[..2]);
+        assert_eq!(journal_rows(&store, "redo").len(), 1);
+        drop(store);
+
+        let mut handler = open_handler();
+        assert!(handler.find_task("plan").is_err());
+        handler.redo().unwrap();
+        handler.save().unwrap();
+        drop(handler);
+        assert!(open_handler().find_task("plan").is_ok());
+        assert_eq!(journal_rows(&open(&dir), "undo")[..2], before[..2]);
+    }
+
+    #[test]
+    fn backs_up_once_an_interval() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(DB_FILE_NAME);
+        let save = |id: u32| {
+            let mut store = SqliteStore::open(&path, Duration::ZERO, now()).unwrap();
+            let mut meta = store.load().unwrap();
+            store.insert(id, &task(&id.to_string())).unwrap();
+            meta.next_id = id + 1;
+            store.save(&meta).unwrap();
+            store.backups().len()
+        };
+        assert_eq!(save(1), 0);
+        assert_eq!(save(2), 1);
+        assert_eq!(save(3), 1);
+        age_backup(&path);
+        assert_eq!(save(4), 2);
+
+        let mut store = SqliteStore::open(&path, Duration::ZERO, now()).unwrap();
+        store.replace(Snapshot::default()).unwrap();
+        store.save(&Snapshot::default().meta()).unwrap();
+        assert_eq!(store.backups().len(), 3);
+    }
+}
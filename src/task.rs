@@ -0,0 +1,186 @@
+use std::{cmp::Ordering, collections::BTreeSet, fmt::Display, str::FromStr};
+
+use chrono::NaiveDate;
+use uuid::Uuid;
+
+use crate::{
+    handler::{Interval, Note, Timestamps},
+    recur::Recurrence,
+    result::{Error, SystemError},
+};
+
+/// A task as the store's users see it, with the properties that depend on
+/// other tasks or on the date, like `blocked` and `overdue`, worked out.
+#[derive(Debug)]
+pub struct Task {
+    pub id: u32,
+    pub uuid: Uuid,
+    pub name: String,
+    pub project: Option<String>,
+    pub parent: Option<u32>,
+    pub desc: String,
+    pub status: Status,
+    pub due: Option<NaiveDate>,
+    pub priority: Option<Priority>,
+    pub tags: BTreeSet<String>,
+    pub depends: Vec<u32>,
+    /// Some dependency of the task isn't completed yet.
+    pub blocked: bool,
+    pub recur: Option<Recurrence>,
+    /// Completed instance of a recurring task.
+    pub archived: bool,
+    /// The task is still open and its due date has passed.
+    pub overdue: bool,
+    pub times: Timestamps,
+    pub intervals: Vec<Interval>,
+    pub notes: Vec<Note>,
+}
+
+impl Task {
+    /// Sorts by the first order, the following ones break ties.
+    pub fn sort_by(vec: &mut [Self], orders: &[SortOrder]) {
+        vec.sort_by(|a, b| {
+            orders.iter().fold(Ordering::Equal, |ord, order| {
+                ord.then_with(|| order.compare(a, b))
+            })
+        });
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Default, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    #[default]
+    #[serde(alias = "a")]
+    Active,
+    #[serde(alias = "s")]
+    Stopped,
+    #[serde(alias = "c", alias = "n")]
+    Canceled,
+    #[serde(alias = "f")]
+    Completed,
+}
+
+impl Ord for Status {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (*self as i32).cmp(&(*other as i32))
+    }
+}
+
+impl PartialOrd for Status {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Status {
+    fn eq(&self, other: &Self) -> bool {
+        core::mem::discriminant(self) == core::mem::discriminant(other)
+    }
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Status::Completed => write!(f, "✅"),
+            Status::Active => write!(f, "🟢"),
+            Status::Stopped => write!(f, "🟡"),
+            Status::Canceled => write!(f, "🔴"),
+        }
+    }
+}
+
+impl FromStr for Status {
+    type Err = Error;
+
+    /// Accepts both the short forms used by the CLI and the full names.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "f" | "completed" => Ok(Self::Completed),
+            "a" | "active" => Ok(Self::Active),
+            "s" | "stopped" => Ok(Self::Stopped),
+            "c" | "n" | "canceled" => Ok(Self::Canceled),
+            _ => Err(SystemError::InvalidStatus(s.to_owned()).into()),
+        }
+    }
+}
+
+/// Tasks are high, medium or low priority, or have no priority at all.
+#[derive(
+    serde::Deserialize, serde::Serialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    High,
+    Medium,
+    Low,
+}
+
+impl Display for Priority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Priority::High => write!(f, "H"),
+            Priority::Medium => write!(f, "M"),
+            Priority::Low => write!(f, "L"),
+        }
+    }
+}
+
+impl FromStr for Priority {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "h" | "high" => Ok(Self::High),
+            "m" | "medium" => Ok(Self::Medium),
+            "l" | "low" => Ok(Self::Low),
+            _ => Err(SystemError::InvalidPriority(s.to_owned()).into()),
+        }
+    }
+}
+
+/// Orders tasks by one of their properties.
+pub enum SortOrder {
+    Alphabetical,
+    ReverseAlphabetical,
+    Status,
+    ReverseStatus,
+    Created,
+    ReverseCreated,
+    Modified,
+    ReverseModified,
+    Due,
+    ReverseDue,
+    Priority,
+    ReversePriority,
+}
+
+impl SortOrder {
+    fn compare(&self, a: &Task, b: &Task) -> Ordering {
+        match self {
+            SortOrder::Alphabetical => a.name.cmp(&b.name),
+            SortOrder::ReverseAlphabetical => b.name.cmp(&a.name),
+            SortOrder::Status => a.status.cmp(&b.status),
+            SortOrder::ReverseStatus => b.status.cmp(&a.status),
+            SortOrder::Created => a.times.created_at.cmp(&b.times.created_at),
+            SortOrder::ReverseCreated => b.times.created_at.cmp(&a.times.created_at),
+            SortOrder::Modified => a.times.modified_at.cmp(&b.times.modified_at),
+            SortOrder::ReverseModified => b.times.modified_at.cmp(&a.times.modified_at),
+            SortOrder::Due => missing_last(a.due, b.due, false),
+            SortOrder::ReverseDue => missing_last(a.due, b.due, true),
+            SortOrder::Priority => missing_last(a.priority, b.priority, false),
+            SortOrder::ReversePriority => missing_last(a.priority, b.priority, true),
+        }
+    }
+}
+
+/// Compares optional values, tasks missing the value go last either way.
+fn missing_last<T: Ord>(a: Option<T>, b: Option<T>, reverse: bool) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) if reverse => b.cmp(&a),
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}